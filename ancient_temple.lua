@@ -0,0 +1,13 @@
+-- Scripted events for The Lost Temple
+
+function on_command(verb, object, preposition, indirect)
+    if verb == "pray" and game.room() == "temple_hall" then
+        if game.get_flag("blessed") then
+            print("The statue's smile seems a little warmer, but nothing else happens.")
+        else
+            game.set_flag("blessed", true)
+            print("You kneel before the stone statue. A faint warmth spreads through the hall.")
+        end
+        return true
+    end
+end
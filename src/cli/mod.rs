@@ -1,3 +1,4 @@
 pub mod play;
 pub mod create;
-pub mod validate;
\ No newline at end of file
+pub mod validate;
+pub mod saves;
\ No newline at end of file
@@ -1,11 +1,13 @@
-use crate::{engine::game::Game, story::loader::load_story};
+use crate::{engine::{game::Game, slots::StoreKind}, story::loader::load_story};
 use std::path::PathBuf;
 
-pub async fn handle_play(file: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+pub async fn handle_play(file: PathBuf, store: StoreKind) -> Result<(), Box<dyn std::error::Error>> {
     println!("Starting game: {:?}", file);
     
     let story = load_story(&file)?;
+    let saves = store.open(&story)?;
     let mut game = Game::new(story)?;
+    game.set_save_store(saves);
     
     game.run().await?;
     
@@ -0,0 +1,12 @@
+use crate::{engine::slots::{self, StoreKind}, story::loader::load_story};
+use std::path::PathBuf;
+
+pub async fn handle_saves(file: PathBuf, store: StoreKind) -> Result<(), Box<dyn std::error::Error>> {
+    let story = load_story(&file)?;
+    let saves = store.open(&story)?;
+    
+    println!("Saves for \"{}\" in {}", story.story.title, saves.location());
+    println!("{}", slots::describe_slots(&story, &saves.list()?));
+    
+    Ok(())
+}
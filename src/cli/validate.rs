@@ -5,8 +5,11 @@ pub async fn handle_validate(file: PathBuf) -> Result<(), Box<dyn std::error::Er
     println!("Validating story: {:?}", file);
     
     match load_story(&file) {
-        Ok(_) => {
+        Ok(story) => {
             println!("✓ Story file is valid! :D");
+            for warning in story.asymmetric_exits() {
+                println!("⚠ {} (add `one_way = true` if that's on purpose)", warning);
+            }
             Ok(())
         },
         Err(e) => {
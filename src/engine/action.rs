@@ -0,0 +1,147 @@
+use crate::engine::condition::{Condition, FlagValue};
+use crate::engine::item::Item;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Something the story does when an event fires, e.g. entering a room:
+///
+/// ```toml
+/// [[rooms.hall.on_enter]]
+/// when = "!flag.heard_whisper"
+/// message = "A voice whispers your name."
+/// set = { heard_whisper = true, fear = 2 }
+/// clear = ["calm"]
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Action {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<Condition>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub set: HashMap<String, FlagValue>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clear: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// What happens when the player does something to an item, e.g.
+/// `cut vines with machete`:
+///
+/// ```toml
+/// [[items.vines.reactions]]
+/// verb = "cut"
+/// with = "machete"
+/// message = "You hack the vines apart."
+/// set = { vines_cut = true }
+/// ```
+///
+/// `with` can also be a tag, e.g. `with = "blade"` for anything tagged as one.
+/// Without `with` the reaction fires whatever the item is used with, or on its own.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Reaction {
+    pub verb: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub with: Option<String>, // item ID or tag of the indirect object
+    // The rest is an `Action`, spelled out because flattening it would let typos through
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<Condition>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub set: HashMap<String, FlagValue>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clear: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl Reaction {
+    /// Whether the reaction answers `verb` used with `indirect`, given as its ID and item.
+    pub fn fits(&self, verb: &str, indirect: Option<(&str, &Item)>) -> bool {
+        self.verb.eq_ignore_ascii_case(verb)
+            && self.with.as_deref().is_none_or(|with| {
+                indirect.is_some_and(|(item_id, item)| item_id == with || item.has_tag(with))
+            })
+    }
+
+    pub fn action(&self) -> Action {
+        Action {
+            when: self.when.clone(),
+            set: self.set.clone(),
+            clear: self.clear.clone(),
+            message: self.message.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_action_from_toml() {
+        #[derive(Deserialize)]
+        struct Wrapper {
+            on_enter: Vec<Action>,
+        }
+
+        let wrapper: Wrapper = toml::from_str(r#"
+[[on_enter]]
+when = "!flag.heard_whisper"
+message = "A voice whispers your name."
+set = { heard_whisper = true, fear = 2, mood = "uneasy" }
+clear = ["calm"]
+"#).unwrap();
+
+        let action = &wrapper.on_enter[0];
+        assert_eq!(action.when.as_ref().unwrap().source(), "!flag.heard_whisper");
+        assert_eq!(action.set["heard_whisper"], FlagValue::Bool(true));
+        assert_eq!(action.set["fear"], FlagValue::Int(2));
+        assert_eq!(action.set["mood"], FlagValue::Str("uneasy".to_string()));
+        assert_eq!(action.clear, vec!["calm".to_string()]);
+        assert_eq!(action.message.as_deref(), Some("A voice whispers your name."));
+    }
+    #[test]
+    fn test_reaction_from_toml() {
+        #[derive(Deserialize)]
+        struct Wrapper {
+            reactions: Vec<Reaction>,
+        }
+
+        let wrapper: Wrapper = toml::from_str(r#"
+[[reactions]]
+verb = "cut"
+with = "machete"
+when = "!flag.vines_cut"
+message = "You hack the vines apart."
+set = { vines_cut = true }
+
+[[reactions]]
+verb = "cut"
+message = "You need something sharp."
+"#).unwrap();
+
+        let [with_machete, bare] = &wrapper.reactions[..] else {
+            panic!("Expected two reactions");
+        };
+        assert_eq!(with_machete.with.as_deref(), Some("machete"));
+        assert_eq!(with_machete.set["vines_cut"], FlagValue::Bool(true));
+        let item = Item::default();
+        let sword = Item { tags: vec!["blade".to_string()], ..Item::default() };
+        assert!(with_machete.fits("cut", Some(("machete", &item))));
+        assert!(!with_machete.fits("cut", None));
+        assert!(!with_machete.fits("cut", Some(("spoon", &item))));
+        assert!(!with_machete.fits("cut", Some(("sword", &sword))));
+        assert!(bare.fits("CUT", None));
+        assert!(bare.fits("cut", Some(("spoon", &item))));
+        assert!(!bare.fits("burn", None));
+
+        // `with` can name a tag instead of an item
+        let with_blade = Reaction { with: Some("blade".to_string()), ..bare.clone() };
+        assert!(with_blade.fits("cut", Some(("sword", &sword))));
+        assert!(!with_blade.fits("cut", Some(("spoon", &item))));
+
+        // Typos in the action part are still caught
+        assert!(toml::from_str::<Wrapper>("[[reactions]]\nverb = \"cut\"\nmesage = \"oops\"").is_err());
+    }
+}
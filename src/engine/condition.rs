@@ -0,0 +1,485 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A story flag or variable. Written in TOML as a plain boolean, integer or string.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum FlagValue {
+    Bool(bool),
+    Int(i64),
+    Str(String),
+}
+
+impl FlagValue {
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            FlagValue::Bool(b) => *b,
+            FlagValue::Int(n) => *n != 0,
+            FlagValue::Str(s) => !s.is_empty(),
+        }
+    }
+}
+
+impl fmt::Display for FlagValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlagValue::Bool(b) => write!(f, "{}", b),
+            FlagValue::Int(n) => write!(f, "{}", n),
+            FlagValue::Str(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+/// The game state a condition is evaluated against.
+pub trait Context {
+    fn has_item(&self, item_id: &str) -> bool;
+    fn item_here(&self, item_id: &str) -> bool; // lying in the current room
+    fn flag(&self, name: &str) -> Option<FlagValue>;
+    fn visits(&self, room_id: &str) -> u32;
+    fn first_visit(&self) -> bool; // first time in the current room
+    fn is_dark(&self) -> bool; // current room is dark and nothing gives light
+}
+
+/// A condition written in a story file, e.g.
+/// `has(torch) && flag.lit_torch && visits(hall) > 1`.
+///
+/// Supports `&&`/`and`, `||`/`or`, `!`/`not`, parentheses, the comparisons
+/// `== != < > <= >=`, integer/string/boolean literals, `flag.<name>`, the
+/// functions `has(item)`, `here(item)` and `visits(room)`, and the keywords
+/// `first_visit` and `dark`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = ExprParser { tokens, pos: 0 };
+        let expr = parser.parse_or()?;
+
+        if let Some(token) = parser.peek() {
+            return Err(format!("unexpected `{}` in condition `{}`", token, source));
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            expr,
+        })
+    }
+
+    pub fn evaluate(&self, ctx: &impl Context) -> bool {
+        self.expr.evaluate(ctx).is_truthy()
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+}
+
+impl TryFrom<String> for Condition {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        Condition::parse(&source)
+    }
+}
+
+impl From<Condition> for String {
+    fn from(condition: Condition) -> Self {
+        condition.source
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Literal(FlagValue),
+    Flag(String),
+    Has(String),
+    Here(String),
+    Visits(String),
+    FirstVisit,
+    Dark,
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(Box<Expr>, CompareOp, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+
+impl Expr {
+    fn evaluate(&self, ctx: &impl Context) -> FlagValue {
+        match self {
+            Expr::Literal(value) => value.clone(),
+            Expr::Flag(name) => ctx.flag(name).unwrap_or(FlagValue::Bool(false)),
+            Expr::Has(item) => FlagValue::Bool(ctx.has_item(item)),
+            Expr::Here(item) => FlagValue::Bool(ctx.item_here(item)),
+            Expr::Visits(room) => FlagValue::Int(ctx.visits(room) as i64),
+            Expr::FirstVisit => FlagValue::Bool(ctx.first_visit()),
+            Expr::Dark => FlagValue::Bool(ctx.is_dark()),
+            Expr::Not(inner) => FlagValue::Bool(!inner.evaluate(ctx).is_truthy()),
+            Expr::And(left, right) => {
+                FlagValue::Bool(left.evaluate(ctx).is_truthy() && right.evaluate(ctx).is_truthy())
+            }
+            Expr::Or(left, right) => {
+                FlagValue::Bool(left.evaluate(ctx).is_truthy() || right.evaluate(ctx).is_truthy())
+            }
+            Expr::Compare(left, op, right) => {
+                FlagValue::Bool(compare(&left.evaluate(ctx), *op, &right.evaluate(ctx)))
+            }
+        }
+    }
+}
+
+fn compare(left: &FlagValue, op: CompareOp, right: &FlagValue) -> bool {
+    use std::cmp::Ordering;
+
+    let ordering = match (left, right) {
+        (FlagValue::Int(a), FlagValue::Int(b)) => Some(a.cmp(b)),
+        (FlagValue::Str(a), FlagValue::Str(b)) => Some(a.cmp(b)),
+        (FlagValue::Bool(a), FlagValue::Bool(b)) => Some(a.cmp(b)),
+        _ => None, // values of different types are never equal or ordered
+    };
+
+    match (op, ordering) {
+        (CompareOp::Eq, Some(o)) => o == Ordering::Equal,
+        (CompareOp::Ne, Some(o)) => o != Ordering::Equal,
+        (CompareOp::Ne, None) => true,
+        (CompareOp::Lt, Some(o)) => o == Ordering::Less,
+        (CompareOp::Gt, Some(o)) => o == Ordering::Greater,
+        (CompareOp::Le, Some(o)) => o != Ordering::Greater,
+        (CompareOp::Ge, Some(o)) => o != Ordering::Less,
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Int(i64),
+    Str(String),
+    Dot,
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Op(CompareOp),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(name) => write!(f, "{}", name),
+            Token::Int(n) => write!(f, "{}", n),
+            Token::Str(s) => write!(f, "\"{}\"", s),
+            Token::Dot => write!(f, "."),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::And => write!(f, "&&"),
+            Token::Or => write!(f, "||"),
+            Token::Not => write!(f, "!"),
+            Token::Op(op) => write!(f, "{}", match op {
+                CompareOp::Eq => "==",
+                CompareOp::Ne => "!=",
+                CompareOp::Lt => "<",
+                CompareOp::Gt => ">",
+                CompareOp::Le => "<=",
+                CompareOp::Ge => ">=",
+            }),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        match c {
+            ' ' | '\t' | '\n' | '\r' => i += 1,
+            '(' => { tokens.push(Token::LParen); i += 1; }
+            ')' => { tokens.push(Token::RParen); i += 1; }
+            '.' => { tokens.push(Token::Dot); i += 1; }
+            '&' if next == Some('&') => { tokens.push(Token::And); i += 2; }
+            '|' if next == Some('|') => { tokens.push(Token::Or); i += 2; }
+            '=' if next == Some('=') => { tokens.push(Token::Op(CompareOp::Eq)); i += 2; }
+            '!' if next == Some('=') => { tokens.push(Token::Op(CompareOp::Ne)); i += 2; }
+            '<' if next == Some('=') => { tokens.push(Token::Op(CompareOp::Le)); i += 2; }
+            '>' if next == Some('=') => { tokens.push(Token::Op(CompareOp::Ge)); i += 2; }
+            '!' => { tokens.push(Token::Not); i += 1; }
+            '<' => { tokens.push(Token::Op(CompareOp::Lt)); i += 1; }
+            '>' => { tokens.push(Token::Op(CompareOp::Gt)); i += 1; }
+            '"' | '\'' => {
+                let end = chars[i + 1..].iter().position(|&ch| ch == c)
+                    .ok_or_else(|| format!("unterminated string in condition `{}`", source))?;
+                tokens.push(Token::Str(chars[i + 1..i + 1 + end].iter().collect()));
+                i += end + 2;
+            }
+            c if c.is_ascii_digit() || (c == '-' && next.is_some_and(|n| n.is_ascii_digit())) => {
+                let start = i;
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                let n = text.parse().map_err(|_| format!("number `{}` is out of range", text))?;
+                tokens.push(Token::Int(n));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                tokens.push(match word.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Ident(word),
+                });
+            }
+            other => return Err(format!("unexpected character `{}` in condition `{}`", other, source)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct ExprParser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl ExprParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(format!("expected `{}` but found `{}`", expected, token)),
+            None => Err(format!("expected `{}` but the condition ended", expected)),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            left = Expr::Or(Box::new(left), Box::new(self.parse_and()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_not()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            left = Expr::And(Box::new(left), Box::new(self.parse_not()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, String> {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, String> {
+        let left = self.parse_primary()?;
+        if let Some(Token::Op(op)) = self.peek().cloned() {
+            self.next();
+            let right = self.parse_primary()?;
+            return Ok(Expr::Compare(Box::new(left), op, Box::new(right)));
+        }
+        Ok(left)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::LParen) => {
+                let expr = self.parse_or()?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::Int(n)) => Ok(Expr::Literal(FlagValue::Int(n))),
+            Some(Token::Str(s)) => Ok(Expr::Literal(FlagValue::Str(s))),
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => Ok(Expr::Literal(FlagValue::Bool(true))),
+                "false" => Ok(Expr::Literal(FlagValue::Bool(false))),
+                "flag" => {
+                    self.expect(Token::Dot)?;
+                    Ok(Expr::Flag(self.parse_ident()?))
+                }
+                "has" => Ok(Expr::Has(self.parse_argument()?)),
+                "here" => Ok(Expr::Here(self.parse_argument()?)),
+                "visits" => Ok(Expr::Visits(self.parse_argument()?)),
+                "first_visit" => Ok(Expr::FirstVisit),
+                "dark" => Ok(Expr::Dark),
+                other => Err(format!("unknown name `{}` in condition", other)),
+            },
+            Some(token) => Err(format!("unexpected `{}` in condition", token)),
+            None => Err("condition ended unexpectedly".to_string()),
+        }
+    }
+
+    fn parse_ident(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Ident(name)) => Ok(name),
+            Some(token) => Err(format!("expected a name but found `{}`", token)),
+            None => Err("expected a name but the condition ended".to_string()),
+        }
+    }
+
+    fn parse_argument(&mut self) -> Result<String, String> {
+        self.expect(Token::LParen)?;
+        let argument = match self.next() {
+            Some(Token::Ident(name)) | Some(Token::Str(name)) => name,
+            Some(token) => return Err(format!("expected an ID but found `{}`", token)),
+            None => return Err("expected an ID but the condition ended".to_string()),
+        };
+        self.expect(Token::RParen)?;
+        Ok(argument)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, HashSet};
+
+    #[derive(Default)]
+    struct TestContext {
+        inventory: HashSet<String>,
+        room_items: HashSet<String>,
+        flags: HashMap<String, FlagValue>,
+        visits: HashMap<String, u32>,
+        first_visit: bool,
+        dark: bool,
+    }
+
+    impl Context for TestContext {
+        fn has_item(&self, item_id: &str) -> bool {
+            self.inventory.contains(item_id)
+        }
+
+        fn item_here(&self, item_id: &str) -> bool {
+            self.room_items.contains(item_id)
+        }
+
+        fn flag(&self, name: &str) -> Option<FlagValue> {
+            self.flags.get(name).cloned()
+        }
+
+        fn visits(&self, room_id: &str) -> u32 {
+            self.visits.get(room_id).copied().unwrap_or(0)
+        }
+
+        fn first_visit(&self) -> bool {
+            self.first_visit
+        }
+
+        fn is_dark(&self) -> bool {
+            self.dark
+        }
+    }
+
+    fn check(source: &str, ctx: &TestContext) -> bool {
+        Condition::parse(source).unwrap().evaluate(ctx)
+    }
+
+    #[test]
+    fn test_condition_with_items_flags_and_visits() {
+        let mut ctx = TestContext::default();
+        let source = "has(torch) && flag.lit_torch && visits(hall) > 1";
+        assert!(!check(source, &ctx));
+
+        ctx.inventory.insert("torch".to_string());
+        ctx.flags.insert("lit_torch".to_string(), FlagValue::Bool(true));
+        ctx.visits.insert("hall".to_string(), 1);
+        assert!(!check(source, &ctx));
+
+        ctx.visits.insert("hall".to_string(), 2);
+        assert!(check(source, &ctx));
+    }
+
+    #[test]
+    fn test_condition_operators() {
+        let mut ctx = TestContext::default();
+        ctx.flags.insert("gold".to_string(), FlagValue::Int(5));
+        ctx.flags.insert("mood".to_string(), FlagValue::Str("angry".to_string()));
+
+        assert!(check("!flag.missing", &ctx));
+        assert!(check("not flag.missing and true", &ctx));
+        assert!(check("flag.gold >= 5 && flag.gold < 10", &ctx));
+        assert!(check("flag.gold == 5 || has(anything)", &ctx));
+        assert!(check("flag.mood == \"angry\"", &ctx));
+        assert!(check("flag.mood != 'happy'", &ctx));
+        assert!(check("(flag.missing || flag.gold) && !(flag.gold > 5)", &ctx));
+        assert!(!check("flag.mood == 5", &ctx));
+    }
+
+    #[test]
+    fn test_condition_room_state() {
+        let mut ctx = TestContext::default();
+        assert!(!check("first_visit", &ctx));
+        assert!(!check("dark", &ctx));
+        assert!(!check("here(lamp)", &ctx));
+
+        ctx.first_visit = true;
+        ctx.dark = true;
+        ctx.room_items.insert("lamp".to_string());
+        assert!(check("first_visit && dark && here(lamp)", &ctx));
+        assert!(!check("has(lamp)", &ctx));
+    }
+
+    #[test]
+    fn test_condition_parse_errors() {
+        assert!(Condition::parse("has(torch").is_err());
+        assert!(Condition::parse("flag.").is_err());
+        assert!(Condition::parse("glows(torch)").is_err());
+        assert!(Condition::parse("has(torch) &&").is_err());
+        assert!(Condition::parse("flag.a flag.b").is_err());
+        assert!(Condition::parse("flag.name == \"oops").is_err());
+    }
+
+    #[test]
+    fn test_condition_deserializes_from_string() {
+        #[derive(Deserialize)]
+        struct Wrapper {
+            when: Condition,
+        }
+
+        let wrapper: Wrapper = toml::from_str(r#"when = "has(key)""#).unwrap();
+        assert_eq!(wrapper.when.source(), "has(key)");
+
+        let result: Result<Wrapper, _> = toml::from_str(r#"when = "has(key"#);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,146 @@
+use crate::engine::condition::{Condition, FlagValue};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Where every conversation with an NPC begins, unless an earlier one said otherwise.
+pub const START: &str = "start";
+
+/// One step of a conversation: what the NPC says and the numbered answers
+/// the player can pick from. Conversations begin at the `start` node:
+///
+/// ```toml
+/// [npcs.ghost.dialogue.start]
+/// text = "\"Have you seen my book?\""
+///
+/// [[npcs.ghost.dialogue.start.choices]]
+/// text = "Is this it?"
+/// when = "has(ancient_book)"
+/// take = ["ancient_book"]
+/// set = { ghost_at_rest = true }
+/// goto = "thanks"
+///
+/// [[npcs.ghost.dialogue.start.choices]]
+/// text = "Goodbye."
+/// ```
+///
+/// A node the player has no answers to ends the conversation. `next` is
+/// where the following conversation begins once this node has ended one.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct DialogueNode {
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub choices: Vec<Choice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>, // node ID
+}
+
+/// Something the player can say. Without `goto` it ends the conversation;
+/// with `goto` and `end` it shows that node and then ends anyway. It's only
+/// offered while `when` holds and the player carries everything in `take`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Choice {
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<Condition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub goto: Option<String>, // node ID
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub end: bool,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub set: HashMap<String, FlagValue>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clear: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub give: Vec<String>, // item IDs the NPC hands the player
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub take: Vec<String>, // item IDs the player hands over
+}
+
+/// Problems with a dialogue that would leave a conversation stuck: nodes
+/// that lead nowhere and items that don't exist.
+pub fn check(dialogue: &HashMap<String, DialogueNode>, item_exists: impl Fn(&str) -> bool) -> Result<(), String> {
+    if dialogue.is_empty() {
+        return Ok(());
+    }
+    if !dialogue.contains_key(START) {
+        return Err(format!("the dialogue has no `{}` node", START));
+    }
+
+    let mut node_ids: Vec<&String> = dialogue.keys().collect();
+    node_ids.sort();
+    for node_id in node_ids {
+        let node = &dialogue[node_id];
+        let targets = node.next.iter().chain(node.choices.iter().flat_map(|choice| &choice.goto));
+        for target in targets {
+            if !dialogue.contains_key(target) {
+                return Err(format!("dialogue node `{}` leads to an unknown node `{}`", node_id, target));
+            }
+        }
+        let items = node.choices.iter().flat_map(|choice| choice.give.iter().chain(&choice.take));
+        for item_id in items {
+            if !item_exists(item_id) {
+                return Err(format!("dialogue node `{}` refers to an unknown item `{}`", node_id, item_id));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dialogue(toml: &str) -> HashMap<String, DialogueNode> {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn test_dialogue_from_toml() {
+        let nodes = dialogue(r#"
+[start]
+text = "Have you seen my book?"
+
+[[start.choices]]
+text = "Is this it?"
+when = "has(book)"
+take = ["book"]
+set = { ghost_at_rest = true }
+goto = "thanks"
+
+[[start.choices]]
+text = "Goodbye."
+
+[thanks]
+text = "Thank you."
+next = "rest"
+
+[rest]
+text = "She smiles."
+"#);
+
+        let start = &nodes["start"];
+        assert_eq!(start.choices.len(), 2);
+        assert_eq!(start.choices[0].when.as_ref().unwrap().source(), "has(book)");
+        assert_eq!(start.choices[0].take, ["book"]);
+        assert_eq!(start.choices[0].goto.as_deref(), Some("thanks"));
+        assert!(start.choices[1].goto.is_none());
+        assert_eq!(nodes["thanks"].next.as_deref(), Some("rest"));
+
+        assert!(check(&nodes, |item_id| item_id == "book").is_ok());
+        assert_eq!(check(&nodes, |_| false).unwrap_err(), "dialogue node `start` refers to an unknown item `book`");
+        assert!(check(&HashMap::new(), |_| false).is_ok());
+    }
+
+    #[test]
+    fn test_broken_dialogue() {
+        let nodes = dialogue("[hello]\ntext = \"Hi.\"");
+        assert_eq!(check(&nodes, |_| true).unwrap_err(), "the dialogue has no `start` node");
+
+        let nodes = dialogue("[start]\ntext = \"Hi.\"\nchoices = [{ text = \"Bye.\", goto = \"bye\" }]");
+        assert_eq!(check(&nodes, |_| true).unwrap_err(), "dialogue node `start` leads to an unknown node `bye`");
+
+        assert!(toml::from_str::<HashMap<String, DialogueNode>>("[start]\ntext = \"Hi.\"\nchoices = [{ text = \"Bye.\", got = \"bye\" }]").is_err());
+    }
+}
@@ -0,0 +1,248 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The directions every story gets: name, short forms, opposite.
+const BUILT_IN: [(&str, &[&str], &str); 12] = [
+    ("north", &["n"], "south"),
+    ("south", &["s"], "north"),
+    ("east", &["e"], "west"),
+    ("west", &["w"], "east"),
+    ("northeast", &["ne"], "southwest"),
+    ("southwest", &["sw"], "northeast"),
+    ("northwest", &["nw"], "southeast"),
+    ("southeast", &["se"], "northwest"),
+    ("up", &["u"], "down"),
+    ("down", &["d"], "up"),
+    ("in", &["inside"], "out"),
+    ("out", &["outside"], "in"),
+];
+
+/// The full name of a built-in direction, e.g. `ne` -> `northeast`, without
+/// building a `Directions` list first.
+pub fn built_in(word: &str) -> Option<&'static str> {
+    BUILT_IN.iter()
+        .find(|(name, aliases, _)| name.eq_ignore_ascii_case(word) || aliases.iter().any(|alias| alias.eq_ignore_ascii_case(word)))
+        .map(|(name, _, _)| *name)
+}
+
+/// A direction a story declares, or more words for a built-in one. Written
+/// either as a list of aliases or as a table when it has an opposite:
+///
+/// ```toml
+/// [directions]
+/// inside = ["in", "enter"]
+/// back = { aliases = ["return"], opposite = "onward" }
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(from = "DirectionDefRepr", into = "DirectionDefRepr")]
+pub struct DirectionDef {
+    pub aliases: Vec<String>,
+    pub opposite: Option<String>,
+}
+
+// On-disk shape of a direction. Ones without an opposite are written back as
+// plain alias lists.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum DirectionDefRepr {
+    Aliases(Vec<String>),
+    Detailed(DetailedDirection),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DetailedDirection {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    aliases: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    opposite: Option<String>,
+}
+
+impl From<DirectionDefRepr> for DirectionDef {
+    fn from(def: DirectionDefRepr) -> Self {
+        match def {
+            DirectionDefRepr::Aliases(aliases) => Self { aliases, opposite: None },
+            DirectionDefRepr::Detailed(direction) => Self {
+                aliases: direction.aliases,
+                opposite: direction.opposite,
+            },
+        }
+    }
+}
+
+impl From<DirectionDef> for DirectionDefRepr {
+    fn from(direction: DirectionDef) -> Self {
+        match direction.opposite {
+            None => DirectionDefRepr::Aliases(direction.aliases),
+            opposite => DirectionDefRepr::Detailed(DetailedDirection {
+                aliases: direction.aliases,
+                opposite,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Direction {
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub opposite: Option<String>,
+}
+
+impl Direction {
+    fn is_called(&self, word: &str) -> bool {
+        self.name.eq_ignore_ascii_case(word) || self.aliases.iter().any(|alias| alias.eq_ignore_ascii_case(word))
+    }
+}
+
+/// Every direction the player can use, built in or declared by the story,
+/// and which way is back.
+#[derive(Debug, Clone)]
+pub struct Directions {
+    list: Vec<Direction>, // story-only directions first, so their aliases win
+}
+
+impl Default for Directions {
+    fn default() -> Self {
+        Self {
+            list: BUILT_IN.iter()
+                .map(|(name, aliases, opposite)| Direction {
+                    name: name.to_string(),
+                    aliases: aliases.iter().map(|alias| alias.to_string()).collect(),
+                    opposite: Some(opposite.to_string()),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Directions {
+    /// The built-in directions plus the ones in the story's `[directions]`.
+    /// Declaring a built-in direction adds aliases to it (and can change its
+    /// opposite); an opposite works both ways even if only one side says so.
+    pub fn for_story(declared: &HashMap<String, DirectionDef>) -> Self {
+        let mut directions = Self::default();
+        let mut names: Vec<&String> = declared.keys().collect();
+        names.sort();
+
+        let mut added = Vec::new();
+        for name in names {
+            let def = &declared[name];
+            match directions.list.iter_mut().find(|direction| direction.name.eq_ignore_ascii_case(name)) {
+                Some(direction) => {
+                    direction.aliases.extend(def.aliases.iter().cloned());
+                    if def.opposite.is_some() {
+                        direction.opposite = def.opposite.clone();
+                    }
+                }
+                None => added.push(Direction {
+                    name: name.to_lowercase(),
+                    aliases: def.aliases.clone(),
+                    opposite: def.opposite.clone(),
+                }),
+            }
+        }
+        added.append(&mut directions.list);
+        directions.list = added;
+
+        let pairs: Vec<(String, String)> = directions.list.iter()
+            .filter_map(|direction| Some((direction.name.clone(), direction.opposite.clone()?)))
+            .collect();
+        for (name, opposite) in pairs {
+            match directions.list.iter_mut().find(|direction| direction.name.eq_ignore_ascii_case(&opposite)) {
+                Some(direction) if direction.opposite.is_none() => direction.opposite = Some(name),
+                Some(_) => {}
+                None => directions.list.push(Direction {
+                    name: opposite.to_lowercase(),
+                    aliases: Vec::new(),
+                    opposite: Some(name),
+                }),
+            }
+        }
+        directions
+    }
+
+    pub fn get(&self, word: &str) -> Option<&Direction> {
+        self.list.iter().find(|direction| direction.is_called(word))
+    }
+
+    /// The direction's full name if `word` is one of its names, e.g. `ne` -> `northeast`.
+    pub fn canonical(&self, word: &str) -> Option<&str> {
+        self.get(word).map(|direction| direction.name.as_str())
+    }
+
+    /// The way back from `word`, e.g. `up` -> `down`.
+    pub fn opposite(&self, word: &str) -> Option<&str> {
+        self.get(word).and_then(|direction| direction.opposite.as_deref())
+    }
+
+    /// Whether two words name the same direction, e.g. an exit called `n`
+    /// and the player typing `north`. Words that aren't directions only match
+    /// themselves.
+    pub fn same(&self, a: &str, b: &str) -> bool {
+        match (self.canonical(a), self.canonical(b)) {
+            (Some(a), Some(b)) => a == b,
+            _ => a.eq_ignore_ascii_case(b),
+        }
+    }
+
+    /// Every name and alias, for spelling suggestions.
+    pub fn words(&self) -> impl Iterator<Item = &str> {
+        self.list.iter()
+            .flat_map(|direction| std::iter::once(&direction.name).chain(&direction.aliases))
+            .map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_built_in_directions() {
+        let directions = Directions::default();
+        assert_eq!(directions.canonical("n"), Some("north"));
+        assert_eq!(directions.canonical("NE"), Some("northeast"));
+        assert_eq!(directions.canonical("u"), Some("up"));
+        assert_eq!(directions.canonical("inside"), Some("in"));
+        assert_eq!(directions.canonical("sideways"), None);
+        assert_eq!(built_in("NE"), Some("northeast"));
+        assert_eq!(built_in("outside"), Some("out"));
+        assert_eq!(built_in("sideways"), None);
+
+        assert_eq!(directions.opposite("north"), Some("south"));
+        assert_eq!(directions.opposite("sw"), Some("northeast"));
+        assert_eq!(directions.opposite("d"), Some("up"));
+        assert_eq!(directions.opposite("out"), Some("in"));
+
+        assert!(directions.same("n", "north"));
+        assert!(directions.same("se", "southeast"));
+        assert!(!directions.same("n", "s"));
+        assert!(directions.same("trapdoor", "Trapdoor"));
+        assert!(!directions.same("trapdoor", "north"));
+    }
+
+    #[test]
+    fn test_story_directions() {
+        let declared: HashMap<String, DirectionDef> = toml::from_str(r#"
+inside = ["in", "enter"]
+back = { aliases = ["return"], opposite = "onward" }
+north = ["fore"]
+"#).unwrap();
+        let directions = Directions::for_story(&declared);
+
+        // The story's aliases win over built-in names
+        assert_eq!(directions.canonical("in"), Some("inside"));
+        assert_eq!(directions.canonical("enter"), Some("inside"));
+        assert_eq!(directions.canonical("fore"), Some("north"));
+        assert_eq!(directions.canonical("n"), Some("north"));
+        assert_eq!(directions.opposite("fore"), Some("south"));
+
+        // Opposites work both ways
+        assert_eq!(directions.opposite("return"), Some("onward"));
+        assert_eq!(directions.opposite("onward"), Some("back"));
+        assert_eq!(directions.opposite("inside"), None);
+
+        assert!(toml::from_str::<HashMap<String, DirectionDef>>(r#"back = { alias = ["return"] }"#).is_err());
+    }
+}
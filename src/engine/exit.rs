@@ -0,0 +1,186 @@
+use crate::engine::condition::Condition;
+use serde::{Deserialize, Serialize};
+
+/// A way out of a room. Story files can write an exit either as a bare
+/// room ID (`north = "hallway"`) or as a table when it needs more than that:
+///
+/// ```toml
+/// east = { to = "vault", locked = true, key = "brass_key", unlock_message = "Click!" }
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(from = "ExitDef", into = "ExitDef")]
+pub struct Exit {
+    pub to: String, // room_id
+    pub locked: bool,
+    pub key: Option<String>, // item ID that unlocks this exit
+    pub requires_flag: Option<String>,
+    pub condition: Option<Condition>, // the exit is blocked while this is false
+    pub blocked_message: Option<String>,
+    pub unlock_message: Option<String>,
+    pub one_way: bool, // no way back is expected, so none is added or asked for
+}
+
+impl Exit {
+    pub fn new(to: &str) -> Self {
+        Self {
+            to: to.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn is_plain(&self) -> bool {
+        !self.locked
+            && self.key.is_none()
+            && self.requires_flag.is_none()
+            && self.condition.is_none()
+            && self.blocked_message.is_none()
+            && self.unlock_message.is_none()
+            && !self.one_way
+    }
+}
+
+// On-disk shape of an exit. Plain exits are written back as bare strings so
+// simple stories stay simple after a round trip.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum ExitDef {
+    Room(String),
+    Detailed(DetailedExit),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DetailedExit {
+    to: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    locked: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    requires_flag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    condition: Option<Condition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    blocked_message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    unlock_message: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    one_way: bool,
+}
+
+impl From<ExitDef> for Exit {
+    fn from(def: ExitDef) -> Self {
+        match def {
+            ExitDef::Room(to) => Self {
+                to,
+                ..Default::default()
+            },
+            ExitDef::Detailed(exit) => Self {
+                to: exit.to,
+                locked: exit.locked,
+                key: exit.key,
+                requires_flag: exit.requires_flag,
+                condition: exit.condition,
+                blocked_message: exit.blocked_message,
+                unlock_message: exit.unlock_message,
+                one_way: exit.one_way,
+            },
+        }
+    }
+}
+
+impl From<Exit> for ExitDef {
+    fn from(exit: Exit) -> Self {
+        if exit.is_plain() {
+            return ExitDef::Room(exit.to);
+        }
+
+        ExitDef::Detailed(DetailedExit {
+            to: exit.to,
+            locked: exit.locked,
+            key: exit.key,
+            requires_flag: exit.requires_flag,
+            condition: exit.condition,
+            blocked_message: exit.blocked_message,
+            unlock_message: exit.unlock_message,
+            one_way: exit.one_way,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Wrapper {
+        north: Exit,
+    }
+
+    #[test]
+    fn test_exit_from_string() {
+        let wrapper: Wrapper = toml::from_str(r#"north = "hallway""#).unwrap();
+        assert_eq!(wrapper.north, Exit::new("hallway"));
+    }
+
+    #[test]
+    fn test_exit_from_table() {
+        let wrapper: Wrapper = toml::from_str(r#"north = { to = "hallway" }"#).unwrap();
+        assert_eq!(wrapper.north, Exit::new("hallway"));
+    }
+
+    #[test]
+    fn test_exit_rejects_unknown_fields() {
+        let result: Result<Wrapper, _> = toml::from_str(r#"north = { to = "hallway", colour = "red" }"#);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_locked_exit() {
+        let wrapper: Wrapper = toml::from_str(r#"
+north = { to = "vault", locked = true, key = "brass_key", blocked_message = "The vault door is shut.", unlock_message = "Click!" }
+"#).unwrap();
+
+        let exit = wrapper.north;
+        assert_eq!(exit.to, "vault");
+        assert!(exit.locked);
+        assert_eq!(exit.key.as_deref(), Some("brass_key"));
+        assert_eq!(exit.blocked_message.as_deref(), Some("The vault door is shut."));
+        assert_eq!(exit.unlock_message.as_deref(), Some("Click!"));
+        assert!(exit.requires_flag.is_none());
+    }
+
+    #[test]
+    fn test_exit_with_condition() {
+        let wrapper: Wrapper = toml::from_str(r#"north = { to = "cave", condition = "has(torch) || flag.cave_lit" }"#).unwrap();
+        assert_eq!(wrapper.north.condition.unwrap().source(), "has(torch) || flag.cave_lit");
+
+        let result: Result<Wrapper, _> = toml::from_str(r#"north = { to = "cave", condition = "has(torch" }"#);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_one_way_exit() {
+        let wrapper: Wrapper = toml::from_str(r#"north = { to = "pit", one_way = true }"#).unwrap();
+        assert!(wrapper.north.one_way);
+        assert!(!Exit::new("pit").one_way);
+
+        let serialized = toml::to_string(&wrapper).unwrap();
+        assert!(serialized.contains("one_way = true"));
+    }
+
+    #[test]
+    fn test_exit_round_trip() {
+        let plain = toml::to_string(&Wrapper { north: Exit::new("hallway") }).unwrap();
+        assert_eq!(plain.trim(), r#"north = "hallway""#);
+
+        let locked = Exit {
+            locked: true,
+            key: Some("brass_key".to_string()),
+            ..Exit::new("vault")
+        };
+        let serialized = toml::to_string(&Wrapper { north: locked.clone() }).unwrap();
+        let restored: Wrapper = toml::from_str(&serialized).unwrap();
+        assert_eq!(restored.north, locked);
+    }
+}
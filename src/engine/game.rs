@@ -1,26 +1,52 @@
-use crate::story::loader::Story;
-use crate::engine::{room::Room, parser::{parse_command, Command}};
+use crate::story::loader::{Story, TypoCorrection};
+use crate::engine::{
+    room::Room,
+    npc::Npc,
+    dialogue::{self, Choice, DialogueNode},
+    direction::Directions,
+    action::{Action, Reaction},
+    condition::{Context, FlagValue},
+    parser::{self, parse_command, parse_phrase, replace_pronouns, split_commands, Command, Phrase},
+    spelling,
+    save::{self, ContainerState, NpcState, RoomState, SavedGame, SAVE_VERSION},
+    script::{ScriptEngine, ScriptLimits, ScriptWorld},
+    slots::{self, JsonSaveStore, SaveStore, SlotInfo},
+};
+use mlua::IntoLuaMulti;
+use rand::seq::SliceRandom;
 
 // Import for tests within this file
 #[cfg(test)]
-use crate::story::loader::StoryInfo;
+use crate::story::loader::{StoryInfo, Verb};
 #[cfg(test)]
-use crate::engine::item::Item;
+use crate::engine::{item::Item, exit::Exit, direction::DirectionDef, npc::Trade, condition::Condition, room::DescriptionVariant};
 use crossterm::{
     execute,
     terminal::{Clear, ClearType},
     style::{Print, SetForegroundColor, ResetColor, Color},
 };
-use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
-use std::fs;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::io::{self, Write};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+/// Autosaves rotate through `autosave-1` to `autosave-N`, overwriting the oldest.
+const AUTOSAVE_SLOTS: usize = 3;
+const AUTOSAVE_PREFIX: &str = "autosave-";
+
+/// How many turns `undo` can go back.
+const UNDO_LIMIT: usize = 50;
 
 #[derive(Debug)]
 pub enum GameError {
     InvalidRoom,
     ItemNotFound,
     RoomNotFound,
+    Script(String),
+    ScriptKilled(String), // a script hit its memory or instruction limit
+    Save(String),
+    Ambiguous { noun: String, question: String }, // several items fit what the player typed
+    Refused(String), // the command made sense but couldn't be done, e.g. a locked door; stops a chain and takes no turn
 }
 
 impl std::fmt::Display for GameError {
@@ -29,6 +55,11 @@ impl std::fmt::Display for GameError {
             GameError::InvalidRoom => write!(f, "Invalid room"),
             GameError::ItemNotFound => write!(f, "Item not found"),
             GameError::RoomNotFound => write!(f, "Room not found"),
+            GameError::Script(message) => write!(f, "Script error: {}", message),
+            GameError::ScriptKilled(reason) => write!(f, "Script stopped: it {}", reason),
+            GameError::Save(message) => write!(f, "Couldn't save or load: {}", message),
+            GameError::Ambiguous { question, .. } => write!(f, "{}", question),
+            GameError::Refused(reason) => write!(f, "{}", reason),
         }
     }
 }
@@ -37,31 +68,96 @@ impl std::error::Error for GameError {}
 
 pub struct Game {
     story: Story,
+    directions: Directions, // built in and the story's own
     current_room: String,
     inventory: HashSet<String>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct SavedGame {
-    current_room: String,
-    inventory: HashSet<String>,
+    flags: HashMap<String, FlagValue>,
+    visits: HashMap<String, u32>, // room_id -> times entered
+    turns: u32,
+    script: Option<ScriptEngine>,
+    saves: Box<dyn SaveStore>,
+    started: Instant, // when this session started, or the last load
+    earlier_playtime: u64, // seconds played before `started`
+    last_autosave: Instant,
+    last_autosave_turn: u32,
+    undo: VecDeque<SavedGame>, // world before each recent turn, oldest first
+    redo: Vec<SavedGame>,
+    pending_question: Option<(String, String)>, // command and noun from the last "Which do you mean?"
+    last_command: Option<String>, // repeated by `again`
+    last_objects: Vec<String>, // item IDs `it` and `them` refer to
+    referent: Option<(String, String)>, // name and item ID a pronoun was swapped for, while its command runs
+    patrol_steps: HashMap<String, usize>, // npc_id -> where on its patrol it is
+    talking_to: Option<String>, // npc_id while a conversation waits for an answer
+    dialogue_at: HashMap<String, String>, // npc_id -> node the next or current conversation is at
 }
 
 impl Game {
     pub fn new(story: Story) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut visits = HashMap::new();
+        visits.insert(story.story.start_room.clone(), 1);
+        
+        let script = match &story.script_source {
+            Some(source) => Some(ScriptEngine::new(
+                source,
+                story.story.script.as_deref().unwrap_or("script"),
+                story.rooms.keys().cloned().collect(),
+                story.items.keys().cloned().collect(),
+                ScriptLimits::default(),
+            )?),
+            None => None,
+        };
+        
         Ok(Self {
             current_room: story.story.start_room.clone(),
+            directions: Directions::for_story(&story.directions),
+            saves: Box::new(JsonSaveStore::in_dir(slots::story_save_dir(&story))),
             story,
             inventory: HashSet::new(),
+            flags: HashMap::new(),
+            visits,
+            turns: 0,
+            script,
+            started: Instant::now(),
+            earlier_playtime: 0,
+            last_autosave: Instant::now(),
+            last_autosave_turn: 0,
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            pending_question: None,
+            last_command: None,
+            last_objects: Vec::new(),
+            referent: None,
+            patrol_steps: HashMap::new(),
+            talking_to: None,
+            dialogue_at: HashMap::new(),
         })
     }
     
+    /// Keeps saves somewhere other than the default JSON files.
+    pub fn set_save_store(&mut self, store: Box<dyn SaveStore>) {
+        self.saves = store;
+    }
+    
     pub async fn run(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         self.clear_screen()?;
         println!("=========================================");
         println!("    Welcome to: {}", self.story.story.title);
         println!("=========================================");
         
+        let mut input = spawn_input_reader();
+        
+        if let Some(autosave) = self.latest_autosave()? {
+            print!("Resume from autosave {}? [y/n] ", slots::describe_slot(&self.story, &autosave));
+            io::stdout().flush()?;
+            let answer = input.recv().await.unwrap_or_default();
+            if answer.trim().to_lowercase().starts_with('y') {
+                match self.load(&autosave.name) {
+                    Ok(_) => println!("Welcome back!"),
+                    Err(e) => println!("Error: {}", e),
+                }
+            }
+        }
+        
         // Show initial room
         self.show_room()?;
         
@@ -69,27 +165,44 @@ impl Game {
             print!("> ");
             io::stdout().flush()?;
             
-            let mut input = String::new();
-            io::stdin().read_line(&mut input)?;
+            // Ctrl-C and the end of input both count as quitting
+            let line = tokio::select! {
+                line = input.recv() => line,
+                _ = tokio::signal::ctrl_c() => None,
+            };
+            let Some(line) = line else {
+                println!();
+                self.quit();
+                break;
+            };
             
-            let command = input.trim().to_lowercase();
+            let command = line.trim().to_lowercase();
             
             if command == "quit" || command == "exit" || command == "q" {
-                println!("Thanks for playing! :D");
+                self.quit();
                 break;
             }
             
+            let room_before = self.current_room.clone();
+            let moving = matches!(self.interpret(&command).1, Command::Go(_));
             match self.process_command(&command).await {
                 Ok(output) => {
                     if !output.is_empty() {
                         println!("{}", output);
                     }
                 }
+                Err(GameError::Ambiguous { question: message, .. } | GameError::Refused(message)) => println!("{}", message),
                 Err(e) => println!("Error: {}", e),
             }
             
+            if self.autosave_due() {
+                if let Err(e) = self.autosave() {
+                    println!("Autosave failed: {}", e);
+                }
+            }
+            
             // Show room description again after certain commands
-            if matches!(parse_command(&command), Command::Go(_) | Command::North | Command::South | Command::East | Command::West) {
+            if moving || self.current_room != room_before {
                 self.show_room()?;
             }
         }
@@ -97,22 +210,396 @@ impl Game {
         Ok(())
     }
     
-    async fn process_command(&mut self, input: &str) -> Result<String, GameError> {
-        match parse_command(input) {
+    fn quit(&mut self) {
+        match self.autosave() {
+            Ok(slot) => println!("Progress saved to {}.", slot),
+            Err(e) => println!("Autosave failed: {}", e),
+        }
+        println!("Thanks for playing! :D");
+    }
+    
+    /// Whether `[config]` asks for an autosave now, by turns played or time passed.
+    fn autosave_due(&self) -> bool {
+        let config = &self.story.config;
+        let by_turns = config.auto_save_turns
+            .is_some_and(|turns| turns > 0 && self.turns >= self.last_autosave_turn + turns);
+        let by_time = config.auto_save_interval
+            .is_some_and(|secs| secs > 0 && self.last_autosave.elapsed().as_secs() >= secs);
+        by_turns || by_time
+    }
+    
+    /// Saves to the next autosave slot and returns its name.
+    fn autosave(&mut self) -> Result<String, GameError> {
+        let saves = self.saves.list()?;
+        let age = |slot: &String| {
+            saves.iter()
+                .find(|save| save.name == *slot)
+                .map_or((0, 0), |save| (save.saved.saved_at, save.saved.turns))
+        };
+        // Fill empty slots first, then overwrite the oldest
+        let slot = (1..=AUTOSAVE_SLOTS)
+            .map(|n| format!("{}{}", AUTOSAVE_PREFIX, n))
+            .min_by_key(age)
+            .unwrap_or_else(|| format!("{}1", AUTOSAVE_PREFIX));
+        
+        self.saves.write(&slot, &self.snapshot())?;
+        self.last_autosave = Instant::now();
+        self.last_autosave_turn = self.turns;
+        Ok(slot)
+    }
+    
+    fn latest_autosave(&self) -> Result<Option<SlotInfo>, GameError> {
+        Ok(self.saves.list()?
+            .into_iter()
+            .filter(|slot| slot.name.starts_with(AUTOSAVE_PREFIX))
+            .max_by_key(|slot| (slot.saved.saved_at, slot.saved.turns)))
+    }
+    
+    /// Parses a command in the story's terms: its verb and direction aliases
+    /// become the names they stand for, its own verbs can be used bare, and
+    /// exits can be taken by name (`inside`, `back`). Returns the rewritten
+    /// input with the command.
+    fn interpret(&self, input: &str) -> (String, Command) {
+        let mut words: Vec<String> = input.split_whitespace().map(str::to_string).collect();
+        if let Some(first) = words.first_mut() {
+            if let Some(verb) = self.story.verb_for(first) {
+                *first = verb.to_string();
+            }
+        }
+        // The direction is the word after "go", or the whole command
+        let at = match words.as_slice() {
+            [go, _] if go.eq_ignore_ascii_case("go") => Some(1),
+            [_] => Some(0),
+            _ => None,
+        };
+        if let Some(word) = at.and_then(|at| words.get_mut(at)) {
+            if let Some(direction) = self.directions.canonical(word) {
+                *word = direction.to_string();
+            }
+        }
+        
+        let input = words.join(" ");
+        let first = words.first().map(|word| word.to_lowercase()).unwrap_or_default();
+        let command = match parse_command(&input) {
+            Command::Unknown | Command::Do(_) if self.story.verbs.contains_key(&first) => {
+                Command::Do(parse_phrase(&input).unwrap_or_default())
+            }
+            // Checked here as well as in the parser, which only knows the built-in directions
+            Command::Unknown | Command::Go(_) if words.len() == 1 && self.is_direction(&first) => Command::Go(first),
+            command => command,
+        };
+        (input, command)
+    }
+    
+    /// Whether `word` names a direction or a way out of the current room.
+    fn is_direction(&self, word: &str) -> bool {
+        self.directions.get(word).is_some()
+            || self.story.rooms.get(&self.current_room).is_some_and(|room| room.exits.contains_key(word))
+    }
+    
+    /// Runs one or more commands, split by `.` or `then`, in order. The
+    /// first one that fails stops the rest; if earlier ones did something
+    /// their output is kept and the failure is added to it. `run` feeds it
+    /// what the player types; tests and other front ends can call it directly.
+    pub async fn process_command(&mut self, input: &str) -> Result<String, GameError> {
+        // Commands the player typed, and ones `again` or a pronoun stand for,
+        // with the item ID the pronoun meant
+        let mut queue: VecDeque<(String, bool, Option<String>)> = split_commands(input).into_iter()
+            .map(|command| (command, true, None))
+            .collect();
+        let mut output = Vec::new();
+        
+        while let Some((command, typed, referent)) = queue.pop_front() {
+            let result = if matches!(parse_command(&command), Command::Again) {
+                match self.last_command.clone() {
+                    Some(last) => {
+                        queue.push_front((last, false, None));
+                        continue;
+                    }
+                    None => Err(GameError::Refused("There's nothing to repeat.".to_string())),
+                }
+            } else {
+                if typed {
+                    self.last_command = Some(command.clone());
+                }
+                let objects: Vec<String> = self.last_objects.iter().map(|item_id| self.item_name(item_id).to_string()).collect();
+                match replace_pronouns(&command, &objects) {
+                    Some(commands) if commands.is_empty() => {
+                        Err(GameError::Refused("I'm not sure what you're referring to.".to_string()))
+                    }
+                    Some(commands) => {
+                        for (command, item_id) in commands.into_iter().zip(self.last_objects.clone()).rev() {
+                            queue.push_front((command, false, Some(item_id)));
+                        }
+                        continue;
+                    }
+                    None => {
+                        self.referent = referent.map(|item_id| (self.item_name(&item_id).to_lowercase(), item_id));
+                        let result = self.process_spelled(&command).await;
+                        self.referent = None;
+                        result
+                    }
+                }
+            };
+            
+            match result {
+                Ok(reply) => output.push(reply),
+                Err(e) if !output.is_empty() => {
+                    output.push(e.to_string());
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        
+        output.retain(|line| !line.is_empty());
+        Ok(output.join("\n"))
+    }
+    
+    /// Runs a command, or if a word in it looks misspelt, suggests or runs a
+    /// corrected one instead, as the story's `typo_correction` says. The
+    /// spelling is checked first so scripts only ever see one of them.
+    async fn process_spelled(&mut self, command: &str) -> Result<String, GameError> {
+        let mode = self.story.config.typo_correction;
+        // An answer to "Which do you mean…?" is a word from a name, not a verb
+        let corrected = match mode {
+            TypoCorrection::Off => None,
+            _ if self.pending_question.is_some() => None,
+            _ => self.correct_spelling(command),
+        };
+        let Some(corrected) = corrected else {
+            return self.process_single(command).await;
+        };
+        
+        match mode {
+            TypoCorrection::Correct => {
+                let reply = self.process_single(&corrected).await?;
+                Ok(format!("(taking that as \"{}\")\n{}", corrected, reply))
+            }
+            _ => Err(GameError::Refused(format!("Did you mean \"{}\"?", corrected))),
+        }
+    }
+    
+    /// `command` with misspelt words swapped for the nearest verb, direction
+    /// or name of something in reach. `None` if nothing looks misspelt.
+    fn correct_spelling(&self, command: &str) -> Option<String> {
+        // Save names can be anything
+        if matches!(parse_command(command), Command::Save(_) | Command::Load(_) | Command::Delete(_)) {
+            return None;
+        }
+        let mut directions: Vec<String> = self.directions.words().map(str::to_string).collect();
+        if let Ok(room) = self.get_current_room() {
+            directions.extend(room.exits.keys().cloned());
+        }
+        
+        let mut verbs: Vec<String> = parser::VERBS.map(str::to_string).to_vec();
+        for (verb, definition) in &self.story.verbs {
+            verbs.push(verb.clone());
+            verbs.extend(definition.aliases.iter().cloned());
+        }
+        // Verbs that only appear in reactions are real words too
+        let reactions = self.story.items.values().flat_map(|item| &item.reactions)
+            .chain(self.story.rooms.values().flat_map(|room| &room.reactions));
+        verbs.extend(reactions.map(|reaction| reaction.verb.to_lowercase()));
+        verbs.extend(directions.iter().cloned());
+        
+        let mut nouns = directions;
+        for item_id in self.items_in_reach().unwrap_or_default() {
+            nouns.extend(self.story.items[&item_id].vocabulary());
+        }
+        for (_, npc) in self.npcs_here() {
+            nouns.extend(npc.vocabulary());
+            nouns.extend(npc.topics.keys().flat_map(|topic| topic.split_whitespace()).map(str::to_lowercase));
+        }
+        if let Ok(room) = self.get_current_room() {
+            nouns.extend(room.scenery.keys().flat_map(|noun| noun.split_whitespace()).map(str::to_lowercase));
+        }
+        
+        let mut words: Vec<String> = command.split_whitespace().map(str::to_lowercase).collect();
+        let mut changed = false;
+        for (i, word) in words.iter_mut().enumerate() {
+            let known = if i == 0 { &verbs } else { &nouns };
+            if parser::is_function_word(word) || known.contains(word) {
+                continue;
+            }
+            // The start of a name is fine as it is
+            if i > 0 && known.iter().any(|noun| noun.starts_with(word.as_str())) {
+                continue;
+            }
+            if let Some(fix) = spelling::closest(word, known.iter().map(String::as_str)) {
+                *word = fix.to_string();
+                changed = true;
+            }
+        }
+        changed.then(|| words.join(" "))
+    }
+    
+    async fn process_single(&mut self, input: &str) -> Result<String, GameError> {
+        // After "Which do you mean, the brass key or the iron key?" a bare
+        // "brass" finishes the previous command
+        let input = match self.pending_question.take() {
+            Some((command, noun)) if matches!(self.interpret(input).1, Command::Unknown | Command::Do(_)) => {
+                command.replacen(&noun, &format!("{} {}", input.trim(), noun), 1)
+            }
+            _ => input.to_string(),
+        };
+        let (input, command) = self.interpret(&input);
+        let input = input.as_str();
+        let mut output = Vec::new();
+        
+        let in_world = !matches!(
+            command,
+            Command::Save(_) | Command::Load(_) | Command::Delete(_) | Command::ListSaves
+                | Command::Undo | Command::Redo | Command::Help
+        );
+        // Taken before the script runs, so `undo` takes back what it did too
+        let before = in_world.then(|| self.snapshot());
+        let carried_before = self.inventory.clone();
+        
+        // Scripts get the first look at every command and may handle it themselves
+        let phrase = parse_phrase(input).unwrap_or_default();
+        let (handled, messages) = self.run_hook(
+            "on_command",
+            (phrase.verb.clone(), phrase.object.clone(), phrase.preposition.clone(), phrase.indirect.clone()),
+        )?;
+        output.extend(messages);
+        
+        // Doing anything else walks away from a conversation; `talk to` picks it up again
+        let walked_away = (in_world && !matches!(command, Command::Choose(_))).then(|| self.talking_to.take());
+        if !handled {
+            // Then the story's own reactions, which can stand in for built-in verbs too
+            let result = match in_world.then(|| self.react(&phrase)).transpose() {
+                Ok(Some(Some(reply))) => Ok(reply),
+                Ok(_) => self.execute(command.clone()),
+                Err(e) => Err(e),
+            };
+            if result.is_ok() {
+                self.remember_objects(&command, &carried_before);
+            }
+            match result {
+                Ok(reply) => output.push(reply),
+                Err(e) => {
+                    // Something that can't be done costs no turn and doesn't walk away
+                    // from a conversation, unless the script already changed the world,
+                    // in which case it's a turn like any other and can be undone
+                    if let Some(talking_to) = walked_away {
+                        self.talking_to = talking_to;
+                    }
+                    if let GameError::Refused(reason) = &e {
+                        output.push(reason.clone());
+                    }
+                    if let Some(before) = before.filter(|before| !before.same_world(&self.snapshot())) {
+                        output.extend(self.end_turn(before));
+                    }
+                    return Err(match e {
+                        GameError::Ambiguous { noun, question } => {
+                            self.pending_question = Some((input.to_string(), noun.clone()));
+                            GameError::Ambiguous { noun, question }
+                        }
+                        // Keep anything the script printed before the command failed
+                        GameError::Refused(_) => {
+                            output.retain(|line| !line.is_empty());
+                            GameError::Refused(output.join("\n"))
+                        }
+                        e => e,
+                    });
+                }
+            }
+        }
+        
+        if let Some(before) = before {
+            output.extend(self.end_turn(before));
+        }
+        
+        output.retain(|line| !line.is_empty());
+        Ok(output.join("\n"))
+    }
+    
+    /// Lets a turn pass after a command: NPCs move, `on_turn` runs, and the
+    /// world as it was `before` goes on the undo list if anything changed.
+    fn end_turn(&mut self, before: SavedGame) -> Vec<String> {
+        self.turns += 1;
+        let mut output = self.move_npcs();
+        // The turn has already happened, so a broken `on_turn` is reported
+        // alongside the command's output rather than instead of it
+        match self.run_hook("on_turn", (self.turns,)) {
+            Ok((_, messages)) => output.extend(messages),
+            Err(e) => output.push(e.to_string()),
+        }
+        
+        // A turn spent looking around, with nobody else moving, leaves nothing to undo
+        if !before.same_world(&self.snapshot()) {
+            self.undo.push_back(before);
+            if self.undo.len() > UNDO_LIMIT {
+                self.undo.pop_front();
+            }
+            self.redo.clear();
+        }
+        // Same for a failure to record it
+        if let Err(e) = self.saves.record_turn(&self.snapshot()) {
+            output.push(format!("Recording the turn failed: {}", e));
+        }
+        output
+    }
+    
+    /// Notes which items a command was about, for `it` and `them` later.
+    fn remember_objects(&mut self, command: &Command, carried_before: &HashSet<String>) {
+        // What changed hands is what the command was about, even if a lookalike is still in reach
+        let moved: Vec<String> = match command {
+            Command::TakeAll(_) | Command::Take(_) | Command::TakeFrom(..) => {
+                self.inventory.difference(carried_before).cloned().collect()
+            }
+            Command::DropAll(_) | Command::Drop(_) | Command::PutIn(..) => {
+                carried_before.difference(&self.inventory).cloned().collect()
+            }
+            _ => Vec::new(),
+        };
+        let mut objects: Vec<String> = match command {
+            Command::TakeAll(_) | Command::DropAll(_) => moved,
+            _ if !moved.is_empty() => moved,
+            Command::Take(noun) | Command::Drop(noun) | Command::Examine(noun) | Command::Open(noun)
+                | Command::Close(noun) | Command::Unlock(noun, _) | Command::PutIn(noun, _)
+                | Command::TakeFrom(noun, _) | Command::Do(Phrase { object: Some(noun), .. }) => {
+                let reach = self.items_in_reach().unwrap_or_default();
+                self.find_item(&reach, noun).ok().flatten().into_iter().collect()
+            }
+            _ => return,
+        };
+        if !objects.is_empty() {
+            objects.sort_by(|a, b| self.item_name(a).cmp(self.item_name(b)));
+            self.last_objects = objects;
+        }
+    }
+    
+    fn execute(&mut self, command: Command) -> Result<String, GameError> {
+        match command {
             Command::Look => self.look(),
+            Command::Examine(thing) => self.examine(&thing),
             Command::Go(direction) => self.go(&direction),
-            Command::North => self.go("north"),
-            Command::South => self.go("south"),
-            Command::East => self.go("east"),
-            Command::West => self.go("west"),
             Command::Take(item) => self.take(&item),
+            Command::TakeAll(except) => self.take_all(&except),
+            Command::TakeFrom(item, container) => self.take_from(&item, &container),
+            Command::PutIn(item, container) => self.put_in(&item, &container),
             Command::Drop(item) => self.drop(&item),
+            Command::DropAll(except) => self.drop_all(&except),
+            Command::Unlock(target, key) => self.unlock(&target, key.as_deref()),
+            Command::Open(target) => self.open(&target),
+            Command::Close(target) => self.close(&target),
+            Command::Talk(npc) => self.talk(&npc),
+            Command::Choose(number) => self.choose(number),
+            Command::Give(item, npc) => self.give(&item, npc.as_deref()),
+            Command::Ask(npc, topic) => self.ask(npc.as_deref(), &topic),
             Command::Inventory => self.show_inventory(),
             Command::Save(filename) => self.save(&filename),
             Command::Load(filename) => self.load(&filename),
+            Command::Delete(slot) => self.delete(&slot),
             Command::ListSaves => Ok(self.list_saves()),
+            Command::Undo => self.undo(),
+            Command::Redo => self.redo(),
             Command::Help => Ok(self.help()),
-            Command::Unknown => Ok("I don't understand that command. Type 'help' for available commands. :0".to_string()),
+            Command::Do(phrase) => self.cannot(&phrase),
+            Command::Again => Err(GameError::Refused("There's nothing to repeat.".to_string())), // handled in process_command
+            Command::Unknown => Err(GameError::Refused("I don't understand that command. Type 'help' for available commands. :0".to_string())),
         }
     }
     
@@ -121,7 +608,7 @@ impl Game {
         let mut output = String::new();
         
         output.push_str(&format!("\n{}", room.title));
-        output.push_str(&format!("\n{}", room.description));
+        output.push_str(&format!("\n{}", self.describe_room(room, false)));
         
         if !room.items.is_empty() {
             output.push_str("\n\nYou see: ");
@@ -131,488 +618,3130 @@ impl Game {
                     output.push_str(&item.name);
                 }
             }
+            
+            // Open containers show what's inside them
+            for item_id in &room.items {
+                if let Some(item) = self.story.items.get(item_id) {
+                    if item.container && item.open && !item.contents.is_empty() {
+                        output.push_str(&format!("\nIn the {}: {}", item.name, self.item_names(&item.contents)));
+                    }
+                }
+            }
+        }
+        
+        let npcs = self.npcs_here();
+        if !npcs.is_empty() {
+            output.push('\n');
+            for (_, npc) in npcs {
+                output.push_str(&format!("\n{} is here.", npc.title()));
+            }
         }
         
         if !room.exits.is_empty() {
             output.push_str("\n\nExits: ");
-            let exit_names: Vec<String> = room.exits.keys().cloned().collect();
+            let exit_names: Vec<String> = room.exits.iter()
+                .map(|(direction, exit)| {
+                    if exit.locked {
+                        format!("{} (locked)", direction)
+                    } else {
+                        direction.clone()
+                    }
+                })
+                .collect();
             output.push_str(&exit_names.join(", "));
         }
         
         Ok(output)
     }
     
+    /// Describes an item the player can reach, or a piece of the room's scenery.
+    fn examine(&self, thing: &str) -> Result<String, GameError> {
+        if self.is_dark() {
+            return Err(GameError::Refused("It's too dark to see anything.".to_string()));
+        }
+        if let Some(item_id) = self.find_item(&self.items_in_reach()?, thing)? {
+            let item = &self.story.items[&item_id];
+            let mut output = item.description.clone();
+            if item.container {
+                let state = match (item.open, item.contents.is_empty()) {
+                    (false, _) => format!("The {} is closed.", item.name),
+                    (true, true) => format!("The {} is empty.", item.name),
+                    (true, false) => format!("In the {}: {}", item.name, self.item_names(&item.contents)),
+                };
+                output.push_str(&format!("\n{}", state));
+            }
+            return Ok(output);
+        }
+        if let Some(npc_id) = self.find_npc(thing) {
+            return Ok(self.story.npcs[&npc_id].description.clone());
+        }
+        
+        match self.get_current_room()?.scenery_for(thing) {
+            Some((_, description)) => Ok(description.to_string()),
+            None => Err(GameError::Refused(format!("You don't see any {} here.", thing))),
+        }
+    }
+    
+    /// The current room's exit `word` leads through, by its name or any other
+    /// name for the same direction (`n` for a `north` exit).
+    fn find_exit(&self, word: &str) -> Result<Option<String>, GameError> {
+        let exits = &self.get_current_room()?.exits;
+        if exits.contains_key(word) {
+            return Ok(Some(word.to_string()));
+        }
+        Ok(exits.keys().find(|name| self.directions.same(name, word)).cloned())
+    }
+    
     fn go(&mut self, direction: &str) -> Result<String, GameError> {
+        let Some(direction) = self.find_exit(direction)? else {
+            return Err(GameError::Refused(format!("You can't go {} from here.", direction)));
+        };
+        let direction = direction.as_str();
         let current_room = self.get_current_room()?;
         
-        if let Some(next_room_id) = current_room.exits.get(direction) {
-            if self.story.rooms.contains_key(next_room_id) {
-                self.current_room = next_room_id.clone();
-                Ok(format!("You go {}.", direction))
+        if let Some(exit) = current_room.exits.get(direction) {
+            if exit.locked {
+                return Err(GameError::Refused(exit.blocked_message.clone()
+                    .unwrap_or_else(|| format!("The way {} is locked.", direction))));
+            }
+            let flag_missing = exit.requires_flag.as_ref().is_some_and(|flag| !self.flag_is_set(flag));
+            let condition_failed = exit.condition.as_ref().is_some_and(|condition| !condition.evaluate(self));
+            if flag_missing || condition_failed {
+                return Err(GameError::Refused(exit.blocked_message.clone()
+                    .unwrap_or_else(|| format!("Something stops you from going {}.", direction))));
+            }
+            
+            if self.story.rooms.contains_key(&exit.to) {
+                let next_room_id = exit.to.clone();
+                let mut output = format!("You go {}.", self.directions.canonical(direction).unwrap_or(direction));
+                for message in self.enter_room(&next_room_id)? {
+                    output.push_str(&format!("\n{}", message));
+                }
+                Ok(output)
             } else {
                 Err(GameError::RoomNotFound)
             }
         } else {
-            Ok(format!("You can't go {} from here.", direction))
+            Err(GameError::Refused(format!("You can't go {} from here.", direction)))
         }
     }
     
     fn take(&mut self, item_name: &str) -> Result<String, GameError> {
-        // Find the item by partial name match
-        let mut found_item_id = None;
-        {
-            let current_room = self.get_current_room()?;
-            for item_id in &current_room.items {
-                if let Some(item) = self.story.items.get(item_id) {
-                    if item.name.to_lowercase().contains(&item_name.to_lowercase()) {
-                        found_item_id = Some(item_id.clone());
-                        break;
-                    }
-                }
-            }
+        let room_items = self.get_current_room()?.items.clone();
+        if let Some(item_id) = self.find_item(&room_items, item_name)? {
+            return self.pick_up(&item_id, None);
         }
         
-        if let Some(item_id) = found_item_id {
-            // We need to remove the item from the room and add to inventory
-            if let Some(room) = self.story.rooms.get_mut(&self.current_room) {
-                room.items.retain(|id| id != &item_id);
-            }
-            self.inventory.insert(item_id.clone());
-            
-            if let Some(item) = self.story.items.get(&item_id) {
-                Ok(format!("You take the {}.", item.name))
-            } else {
-                Ok("You take the item.".to_string())
+        // Otherwise look inside open containers in the room
+        let open_containers: Vec<&String> = room_items.iter()
+            .filter(|container_id| {
+                self.story.items.get(*container_id).is_some_and(|item| item.container && item.open)
+            })
+            .collect();
+        let inside: Vec<String> = open_containers.iter()
+            .flat_map(|container_id| self.story.items[*container_id].contents.iter().cloned())
+            .collect();
+        match self.find_item(&inside, item_name)? {
+            Some(item_id) => {
+                let container_id = open_containers.iter()
+                    .find(|container_id| self.story.items[**container_id].contents.contains(&item_id))
+                    .map(|container_id| container_id.to_string());
+                self.pick_up(&item_id, container_id.as_deref())
             }
-        } else {
-            Err(GameError::ItemNotFound)
+            None => match self.get_current_room()?.scenery_for(item_name) {
+                Some((noun, _)) => Err(GameError::Refused(format!("You can't take the {}.", noun))),
+                None => Err(GameError::ItemNotFound),
+            },
         }
     }
     
-    fn drop(&mut self, item_name: &str) -> Result<String, GameError> {
-        // Find the item in inventory by partial name match
-        let mut found_item_id = None;
-        for item_id in &self.inventory {
-            if let Some(item) = self.story.items.get(item_id) {
-                if item.name.to_lowercase().contains(&item_name.to_lowercase()) {
-                    found_item_id = Some(item_id.clone());
-                    break;
-                }
+    /// Takes everything portable lying in the room, apart from `except`.
+    fn take_all(&mut self, except: &[String]) -> Result<String, GameError> {
+        let wanted: Vec<String> = self.get_current_room()?.items.iter()
+            .filter(|item_id| self.story.items.get(*item_id).is_some_and(|item| {
+                item.portable && !except.iter().any(|phrase| item.matches(phrase))
+            }))
+            .cloned()
+            .collect();
+        if wanted.is_empty() {
+            return Err(GameError::Refused("There's nothing here to take.".to_string()));
+        }
+        
+        // One item being too heavy doesn't stop the others
+        let mut output = Vec::new();
+        for item_id in wanted {
+            match self.pick_up(&item_id, None) {
+                Ok(message) => output.push(message),
+                Err(GameError::Refused(reason)) => output.push(reason),
+                Err(e) => return Err(e),
             }
         }
+        Ok(output.join("\n"))
+    }
+    
+    fn take_from(&mut self, item_name: &str, container_name: &str) -> Result<String, GameError> {
+        let Some(container_id) = self.find_container(container_name)? else {
+            return Err(GameError::Refused(format!("You don't see a {} you can take things from.", container_name)));
+        };
+        let container = &self.story.items[&container_id];
+        if !container.open {
+            return Err(GameError::Refused(format!("The {} is closed.", container.name)));
+        }
         
-        if let Some(item_id) = found_item_id {
-            self.inventory.remove(&item_id);
-            if let Some(room) = self.story.rooms.get_mut(&self.current_room) {
-                room.items.push(item_id.clone());
+        match self.find_item(&container.contents, item_name)? {
+            Some(item_id) => self.pick_up(&item_id, Some(&container_id)),
+            None => Err(GameError::Refused(format!("There's no {} in the {}.", item_name, container.name))),
+        }
+    }
+    
+    /// Moves an item into the inventory from the current room, or from
+    /// `container_id` if given, and runs its take actions and hook.
+    fn pick_up(&mut self, item_id: &str, container_id: Option<&str>) -> Result<String, GameError> {
+        if let Some(refusal) = self.refuse_take(item_id) {
+            return Err(GameError::Refused(refusal));
+        }
+        
+        match container_id {
+            Some(container_id) => {
+                if let Some(container) = self.story.items.get_mut(container_id) {
+                    container.contents.retain(|id| id != item_id);
+                }
             }
-            
-            if let Some(item) = self.story.items.get(&item_id) {
-                Ok(format!("You drop the {}.", item.name))
-            } else {
-                Ok("You drop the item.".to_string())
+            None => {
+                if let Some(room) = self.story.rooms.get_mut(&self.current_room) {
+                    room.items.retain(|id| id != item_id);
+                }
             }
-        } else {
-            Err(GameError::ItemNotFound)
         }
+        self.inventory.insert(item_id.to_string());
+        
+        let mut output = match container_id {
+            Some(container_id) => format!("You take the {} from the {}.", self.item_name(item_id), self.item_name(container_id)),
+            None => format!("You take the {}.", self.item_name(item_id)),
+        };
+        let actions = self.story.items.get(item_id).map(|item| item.on_take.clone()).unwrap_or_default();
+        let mut messages = self.apply_actions(&actions);
+        messages.extend(self.run_hook("on_take", (item_id.to_string(),))?.1);
+        for message in messages {
+            output.push_str(&format!("\n{}", message));
+        }
+        Ok(output)
     }
     
-    fn show_inventory(&self) -> Result<String, GameError> {
-        if self.inventory.is_empty() {
-            Ok("Your inventory is empty. :0".to_string())
-        } else {
-            let mut output = "You are carrying:\n".to_string();
-            for item_id in &self.inventory {
-                if let Some(item) = self.story.items.get(item_id) {
-                    output.push_str(&format!("- {}\n", item.name));
-                }
+    /// Explains why the player can't pick an item up, if they can't.
+    fn refuse_take(&self, item_id: &str) -> Option<String> {
+        let item = self.story.items.get(item_id)?;
+        let config = &self.story.config;
+        
+        if !item.portable {
+            return Some(format!("The {} can't be taken.", item.name));
+        }
+        if let Some(max_items) = config.max_inventory_items {
+            if self.inventory.len() >= max_items {
+                return Some(format!(
+                    "You can't carry more than {} things. Drop something first.",
+                    max_items
+                ));
             }
-            // Remove the trailing newline
-            if output.ends_with('\n') {
-                output.pop();
+        }
+        if let Some(max_weight) = config.max_inventory_weight {
+            // Moving something out of a bag you're already carrying weighs nothing extra
+            let carried = self.carried_weight();
+            let weight = if self.is_carried(item_id) { 0 } else { self.total_weight(item_id) };
+            if carried.saturating_add(weight) > max_weight {
+                return Some(format!(
+                    "The {} is too heavy: it weighs {} and you can only carry {} more.",
+                    item.name,
+                    weight,
+                    max_weight.saturating_sub(carried)
+                ));
             }
-            Ok(output)
         }
+        None
     }
     
-    fn save(&self, filename: &str) -> Result<String, GameError> {
-        let save_data = SavedGame {
-            current_room: self.current_room.clone(),
-            inventory: self.inventory.clone(),
+    fn carried_weight(&self) -> u32 {
+        self.inventory.iter()
+            .fold(0, |total, item_id| total.saturating_add(self.total_weight(item_id)))
+    }
+    
+    /// An item's weight plus the weight of everything inside it.
+    fn total_weight(&self, item_id: &str) -> u32 {
+        self.story.items.get(item_id).map_or(0, |item| {
+            item.contents.iter().fold(item.weight, |total, id| total.saturating_add(self.total_weight(id)))
+        })
+    }
+    
+    /// Whether the player has the item, either in hand or inside something they carry.
+    fn is_carried(&self, item_id: &str) -> bool {
+        if self.inventory.contains(item_id) {
+            return true;
+        }
+        self.story.items.iter()
+            .find(|(_, item)| item.contents.iter().any(|id| id == item_id))
+            .is_some_and(|(container_id, _)| self.is_carried(container_id))
+    }
+    
+    fn put_in(&mut self, item_name: &str, container_name: &str) -> Result<String, GameError> {
+        let item_id = self.find_in_inventory(item_name)?.ok_or(GameError::ItemNotFound)?;
+        let Some(container_id) = self.find_container(container_name)? else {
+            return Err(GameError::Refused(format!("You don't see a {} you can put things in.", container_name)));
         };
         
-        let json = serde_json::to_string(&save_data).map_err(|_| GameError::InvalidRoom)?;
-        fs::write(filename, json).map_err(|_| GameError::InvalidRoom)?;
+        if item_id == container_id {
+            return Err(GameError::Refused(format!("You can't put the {} inside itself.", self.item_name(&item_id))));
+        }
+        if !self.story.items[&container_id].open {
+            return Err(GameError::Refused(format!("The {} is closed.", self.item_name(&container_id))));
+        }
         
-        Ok(format!("Game saved to {}", filename))
+        self.inventory.remove(&item_id);
+        if let Some(container) = self.story.items.get_mut(&container_id) {
+            container.contents.push(item_id.clone());
+        }
+        Ok(format!("You put the {} in the {}.", self.item_name(&item_id), self.item_name(&container_id)))
     }
     
-    fn load(&mut self, filename: &str) -> Result<String, GameError> {
-        let json = fs::read_to_string(filename).map_err(|_| GameError::InvalidRoom)?;
-        let save_data: SavedGame = serde_json::from_str(&json).map_err(|_| GameError::InvalidRoom)?;
-        
-        self.current_room = save_data.current_room;
-        self.inventory = save_data.inventory;
-        
-        Ok(format!("Game loaded from {}", filename))
+    fn drop(&mut self, item_name: &str) -> Result<String, GameError> {
+        if let Some(item_id) = self.find_in_inventory(item_name)? {
+            Ok(self.put_down(&item_id))
+        } else {
+            Err(GameError::ItemNotFound)
+        }
     }
     
-    fn get_save_files(&self) -> Vec<String> {
-        let mut saves = Vec::new();
-        if let Ok(entries) = fs::read_dir(".") {
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    let path = entry.path();
-                    if path.extension().map_or(false, |ext| ext == "save") {
-                        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-                            saves.push(name.to_string());
-                        }
-                    }
-                }
-            }
+    fn put_down(&mut self, item_id: &str) -> String {
+        self.inventory.remove(item_id);
+        if let Some(room) = self.story.rooms.get_mut(&self.current_room) {
+            room.items.push(item_id.to_string());
         }
-        saves
+        format!("You drop the {}.", self.item_name(item_id))
     }
     
-    fn list_saves(&self) -> String {
-        let saves = self.get_save_files();
-        if saves.is_empty() {
-            "No save files found. :0".to_string()
-        } else {
-            let mut output = "Available saves:\n".to_string();
-            for save in saves {
-                output.push_str(&format!("- {}\n", save));
-            }
-            // Remove the trailing newline
-            if output.ends_with('\n') {
-                output.pop();
-            }
-            output
+    fn drop_all(&mut self, except: &[String]) -> Result<String, GameError> {
+        let mut unwanted: Vec<String> = self.inventory.iter()
+            .filter(|item_id| self.story.items.get(*item_id).is_some_and(|item| {
+                !except.iter().any(|phrase| item.matches(phrase))
+            }))
+            .cloned()
+            .collect();
+        if unwanted.is_empty() {
+            return Err(GameError::Refused("You don't have anything to drop.".to_string()));
         }
+        unwanted.sort_by(|a, b| self.item_name(a).cmp(self.item_name(b)));
+        
+        let output: Vec<String> = unwanted.iter().map(|item_id| self.put_down(item_id)).collect();
+        Ok(output.join("\n"))
     }
     
-    fn help(&self) -> String {
-        "Available commands:\n- look: Look around the current room\n- go [direction]: Move in a direction (north, south, east, west)\n- n/s/e/w: Short forms for directions\n- take [item]: Pick up an item\n- drop [item]: Drop an item\n- inventory: Check your inventory\n- save [filename]: Save the current game\n- load [filename]: Load a saved game\n- saves: List available saves\n- help: Show this help\n- quit: Exit the game\n\nExample: 'go north' or 'take key' :D".to_string()
+    fn unlock(&mut self, target: &str, key_name: Option<&str>) -> Result<String, GameError> {
+        if let Some(direction) = self.find_exit(target)? {
+            return self.unlock_exit(&direction, key_name);
+        }
+        match self.find_container(target)? {
+            Some(container_id) => self.unlock_container(&container_id, key_name),
+            None => Err(GameError::Refused(format!("There's nothing to unlock to the {}.", target))),
+        }
     }
     
-    fn show_room(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let room = self.get_current_room()?;
+    fn unlock_exit(&mut self, direction: &str, key_name: Option<&str>) -> Result<String, GameError> {
+        let exit = match self.get_current_room()?.exits.get(direction) {
+            Some(exit) => exit.clone(),
+            None => return Err(GameError::Refused(format!("There's nothing to unlock to the {}.", direction))),
+        };
         
-        execute!(
-            io::stdout(),
-            SetForegroundColor(Color::Cyan),
-            Print(format!("{}\n", room.title)),
-            ResetColor
-        )?;
+        if !exit.locked {
+            return Err(GameError::Refused(format!("The way {} isn't locked.", direction)));
+        }
         
-        execute!(
-            io::stdout(),
-            Print(format!("{}\n", room.description)),
-        )?;
+        let Some(key_id) = self.choose_key(exit.key.as_ref(), key_name)? else {
+            return Err(GameError::Refused("You don't have anything to unlock it with.".to_string()));
+        };
+        if exit.key.as_ref() != Some(&key_id) {
+            return Err(GameError::Refused(format!("The {} doesn't work here.", self.item_name(&key_id))));
+        }
         
-        Ok(())
+        if let Some(exit) = self.story.rooms.get_mut(&self.current_room)
+            .and_then(|room| room.exits.get_mut(direction))
+        {
+            exit.locked = false;
+        }
+        
+        match exit.unlock_message {
+            Some(message) => Ok(message),
+            None => Ok(format!("You unlock the way {} with the {}.", direction, self.item_name(&key_id))),
+        }
     }
     
-    fn get_current_room(&self) -> Result<&Room, GameError> {
-        self.story.rooms.get(&self.current_room).ok_or(GameError::InvalidRoom)
-    }
+    fn unlock_container(&mut self, container_id: &str, key_name: Option<&str>) -> Result<String, GameError> {
+        let container = &self.story.items[container_id];
+        if !container.locked {
+            return Err(GameError::Refused(format!("The {} isn't locked.", container.name)));
+        }
+        
+        let required = container.key.clone();
+        let Some(key_id) = self.choose_key(required.as_ref(), key_name)? else {
+            return Err(GameError::Refused("You don't have anything to unlock it with.".to_string()));
+        };
+        if required.as_ref() != Some(&key_id) {
+            return Err(GameError::Refused(format!("The {} doesn't work here.", self.item_name(&key_id))));
+        }
+        
+        if let Some(container) = self.story.items.get_mut(container_id) {
+            container.locked = false;
+        }
+        Ok(format!("You unlock the {} with the {}.", self.item_name(container_id), self.item_name(&key_id)))
+    }
+    
+    /// Works out which carried item the player is using as a key: the one
+    /// they named, or otherwise the right key if they happen to carry it.
+    fn choose_key(&self, required: Option<&String>, key_name: Option<&str>) -> Result<Option<String>, GameError> {
+        match key_name {
+            Some(name) => match self.find_in_inventory(name)? {
+                Some(key_id) => Ok(Some(key_id)),
+                None => Err(GameError::ItemNotFound),
+            },
+            None => Ok(required.filter(|key| self.inventory.contains(*key)).cloned()),
+        }
+    }
+    
+    fn open(&mut self, target: &str) -> Result<String, GameError> {
+        if let Some(direction) = self.find_exit(target)? {
+            return if self.get_current_room()?.exits[&direction].locked {
+                self.unlock_exit(&direction, None)
+            } else {
+                Err(GameError::Refused(format!("The way {} is already open.", direction)))
+            };
+        }
+        
+        let Some(container_id) = self.find_container(target)? else {
+            return Err(GameError::Refused(format!("There's nothing to open to the {}.", target)));
+        };
+        let container = &self.story.items[&container_id];
+        if container.open {
+            return Err(GameError::Refused(format!("The {} is already open.", container.name)));
+        }
+        
+        // A locked container opens straight away if the player carries its key
+        let mut output = Vec::new();
+        if container.locked {
+            match self.unlock_container(&container_id, None) {
+                Ok(message) => output.push(message),
+                Err(GameError::Refused(_)) => {
+                    return Err(GameError::Refused(format!("The {} is locked.", self.item_name(&container_id))));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        
+        if let Some(container) = self.story.items.get_mut(&container_id) {
+            container.open = true;
+        }
+        let container = &self.story.items[&container_id];
+        if container.contents.is_empty() {
+            output.push(format!("You open the {}. It's empty.", container.name));
+        } else {
+            output.push(format!("You open the {}, revealing: {}", container.name, self.item_names(&container.contents)));
+        }
+        Ok(output.join("\n"))
+    }
+    
+    fn close(&mut self, target: &str) -> Result<String, GameError> {
+        let Some(container_id) = self.find_container(target)? else {
+            return Err(GameError::Refused(format!("You don't see a {} you can close.", target)));
+        };
+        let container = self.story.items.get_mut(&container_id).ok_or(GameError::ItemNotFound)?;
+        if !container.open {
+            return Err(GameError::Refused(format!("The {} is already closed.", container.name)));
+        }
+        container.open = false;
+        Ok(format!("You close the {}.", container.name))
+    }
+    
+    /// Finds a container the player can reach, in the room or carried.
+    fn find_container(&self, name: &str) -> Result<Option<String>, GameError> {
+        let room_items = self.get_current_room().map(|room| room.items.as_slice()).unwrap_or_default();
+        let containers = room_items.iter()
+            .chain(self.inventory.iter())
+            .filter(|item_id| self.story.items.get(*item_id).is_some_and(|item| item.container));
+        self.find_item(containers, name)
+    }
+    
+    /// Runs the first reaction that fits the verb and the indirect object,
+    /// looking at the object's own reactions and then the room's. `None` if
+    /// nothing reacts, so built-in handling takes over.
+    fn react(&mut self, phrase: &Phrase) -> Result<Option<String>, GameError> {
+        let reach = self.items_in_reach()?;
+        let room = self.get_current_room()?;
+        
+        // Only work out which items are meant if something here knows the verb
+        let knows_verb = |reactions: &[Reaction]| {
+            reactions.iter().any(|reaction| reaction.verb.eq_ignore_ascii_case(&phrase.verb))
+        };
+        if !knows_verb(&room.reactions) && !reach.iter().any(|item_id| knows_verb(&self.story.items[item_id].reactions)) {
+            return Ok(None);
+        }
+        let object = match &phrase.object {
+            Some(noun) => self.find_item(&reach, noun)?,
+            None => None,
+        };
+        let indirect = match &phrase.indirect {
+            Some(noun) => match self.find_item(&reach, noun)? {
+                Some(indirect_id) => Some(indirect_id),
+                None => return Ok(None),
+            },
+            None => None,
+        };
+        
+        let action = object.iter()
+            .flat_map(|item_id| &self.story.items[item_id].reactions)
+            .chain(&room.reactions)
+            .find(|reaction| {
+                reaction.fits(&phrase.verb, indirect.as_deref().map(|item_id| (item_id, &self.story.items[item_id])))
+                    && reaction.when.as_ref().is_none_or(|condition| condition.evaluate(self))
+            })
+            .map(Reaction::action);
+        Ok(action.map(|action| self.apply_actions(&[action]).join("\n")))
+    }
+    
+    /// Why nothing happened when nothing reacts to a story verb.
+    fn cannot(&self, phrase: &Phrase) -> Result<String, GameError> {
+        let reach = self.items_in_reach()?;
+        let nouns = phrase.object.iter().map(|noun| (None, noun))
+            .chain(phrase.indirect.iter().map(|noun| (phrase.preposition.as_deref(), noun)));
+        
+        let mut output = format!("You can't {}", phrase.verb);
+        for (preposition, noun) in nouns {
+            let name = match self.find_item(&reach, noun)? {
+                Some(item_id) => self.item_name(&item_id).to_string(),
+                None => match self.get_current_room()?.scenery_for(noun) {
+                    Some((scenery, _)) => scenery.to_string(),
+                    None => return Err(GameError::Refused(format!("You don't see any {} here.", noun))),
+                },
+            };
+            if let Some(preposition) = preposition {
+                output.push_str(&format!(" {}", preposition));
+            }
+            output.push_str(&format!(" the {}", name));
+        }
+        output.push('.');
+        
+        // Story verbs can have their own reply
+        Err(GameError::Refused(self.story.verbs.get(&phrase.verb)
+            .and_then(|verb| verb.message.clone())
+            .unwrap_or(output)))
+    }
+    
+    /// The NPCs in the current room, by name.
+    fn npcs_here(&self) -> Vec<(&String, &Npc)> {
+        let mut npcs: Vec<(&String, &Npc)> = self.story.npcs.iter()
+            .filter(|(_, npc)| npc.location == self.current_room)
+            .collect();
+        npcs.sort_by(|(_, a), (_, b)| a.name.cmp(&b.name));
+        npcs
+    }
+    
+    /// The NPC here the player means by `name`, if any.
+    fn find_npc(&self, name: &str) -> Option<String> {
+        self.npcs_here().into_iter()
+            .find(|(_, npc)| npc.matches(name))
+            .map(|(npc_id, _)| npc_id.clone())
+    }
+    
+    fn talk(&mut self, name: &str) -> Result<String, GameError> {
+        let Some(npc_id) = self.find_npc(name) else {
+            return match self.find_item(&self.items_in_reach()?, name) {
+                Ok(Some(item_id)) => Err(GameError::Refused(format!("The {} doesn't answer.", self.item_name(&item_id)))),
+                _ => Err(GameError::Refused(format!("There's no one called {} here.", name))),
+            };
+        };
+        let npc = &self.story.npcs[&npc_id];
+        if npc.dialogue.is_empty() {
+            return Ok(npc.talk.clone().unwrap_or_else(|| format!("{} has nothing to say.", npc.title())));
+        }
+        let node_id = self.dialogue_at.get(&npc_id).cloned().unwrap_or_else(|| dialogue::START.to_string());
+        Ok(self.show_node(&npc_id, &node_id, false))
+    }
+    
+    /// Shows what the NPC says at a conversation node with the answers on
+    /// offer, or ends the conversation there if there are none (or `end`).
+    fn show_node(&mut self, npc_id: &str, node_id: &str, end: bool) -> String {
+        let dialogue = &self.story.npcs[npc_id].dialogue;
+        // A save from before the story was edited might point at a node that's gone
+        let (node_id, node) = match dialogue.get_key_value(node_id).or_else(|| dialogue.get_key_value(dialogue::START)) {
+            Some((node_id, node)) => (node_id.clone(), node),
+            None => return format!("{} has nothing to say.", self.story.npcs[npc_id].title()),
+        };
+        
+        let choices = self.choices(node);
+        if end || choices.is_empty() {
+            let text = node.text.clone();
+            let next = node.next.clone().unwrap_or_else(|| dialogue::START.to_string());
+            self.end_conversation(npc_id, next);
+            return text;
+        }
+        let mut output = node.text.clone();
+        for (i, choice) in choices.iter().enumerate() {
+            output.push_str(&format!("\n{}. {}", i + 1, choice.text));
+        }
+        self.talking_to = Some(npc_id.to_string());
+        self.dialogue_at.insert(npc_id.to_string(), node_id);
+        output
+    }
+    
+    /// The answers a conversation node offers right now: those whose
+    /// condition holds and whose items the player has to hand over.
+    fn choices<'a>(&self, node: &'a DialogueNode) -> Vec<&'a Choice> {
+        node.choices.iter()
+            .filter(|choice| choice.when.as_ref().is_none_or(|condition| condition.evaluate(self)))
+            .filter(|choice| choice.take.iter().all(|item_id| self.inventory.contains(item_id)))
+            .collect()
+    }
+    
+    fn end_conversation(&mut self, npc_id: &str, next: String) {
+        self.talking_to = None;
+        self.dialogue_at.insert(npc_id.to_string(), next);
+    }
+    
+    /// Answers the NPC the player is talking to with the `number`th choice on offer.
+    fn choose(&mut self, number: usize) -> Result<String, GameError> {
+        let not_talking = || GameError::Refused("You're not talking to anyone.".to_string());
+        let npc_id = self.talking_to.clone().ok_or_else(not_talking)?;
+        // They may have walked off, or been moved by a script
+        if self.story.npcs.get(&npc_id).is_none_or(|npc| npc.location != self.current_room) {
+            self.talking_to = None;
+            return Err(not_talking());
+        }
+        let node_id = self.dialogue_at.get(&npc_id).cloned().unwrap_or_else(|| dialogue::START.to_string());
+        let node = self.story.npcs.get(&npc_id)
+            .and_then(|npc| npc.dialogue.get(&node_id))
+            .cloned()
+            .ok_or_else(not_talking)?;
+        
+        let choices = self.choices(&node);
+        let Some(choice) = number.checked_sub(1).and_then(|i| choices.get(i)).map(|choice| (*choice).clone()) else {
+            return Err(GameError::Refused(format!("Choose an answer from 1 to {}.", choices.len())));
+        };
+        
+        let mut output = Vec::new();
+        if choice.goto.is_none() {
+            // With nowhere to go, the player's answer is the last word
+            output.push(format!("\"{}\"", choice.text));
+        }
+        for item_id in &choice.take {
+            self.inventory.remove(item_id);
+            output.push(format!("You hand over the {}.", self.item_name(item_id)));
+        }
+        let title = self.story.npcs[&npc_id].title();
+        for item_id in &choice.give {
+            output.push(self.hand_to_player(item_id, &title));
+        }
+        for (name, value) in &choice.set {
+            self.flags.insert(name.clone(), value.clone());
+        }
+        for name in &choice.clear {
+            self.flags.remove(name);
+        }
+        
+        match &choice.goto {
+            Some(goto) => output.push(self.show_node(&npc_id, goto, choice.end)),
+            None => self.end_conversation(&npc_id, node.next.clone().unwrap_or_else(|| dialogue::START.to_string())),
+        }
+        Ok(output.join("\n"))
+    }
+    
+    /// The NPC the player means: the one they named, or the only one here
+    /// if they didn't say. `doing` fills in the question when it's unclear,
+    /// e.g. "give the lamp to".
+    fn choose_npc(&self, name: Option<&str>, doing: &str) -> Result<String, GameError> {
+        match name {
+            Some(name) => self.find_npc(name)
+                .ok_or_else(|| GameError::Refused(format!("There's no one called {} here.", name))),
+            None => match self.npcs_here().as_slice() {
+                [(npc_id, _)] => Ok(npc_id.to_string()),
+                [] => Err(GameError::Refused(format!("There's no one here to {}.", doing))),
+                _ => Err(GameError::Refused(format!("Who do you want to {}?", doing))),
+            },
+        }
+    }
+    
+    /// Offers a carried item to an NPC, who takes it if one of their trades
+    /// wants it and refuses otherwise.
+    fn give(&mut self, item_name: &str, npc_name: Option<&str>) -> Result<String, GameError> {
+        let Some(item_id) = self.find_in_inventory(item_name)? else {
+            return Err(GameError::ItemNotFound);
+        };
+        let npc_id = self.choose_npc(npc_name, &format!("give the {} to", self.item_name(&item_id)))?;
+        let npc = &self.story.npcs[&npc_id];
+        let trade = npc.trades.iter()
+            .find(|trade| trade.wants == item_id && trade.when.as_ref().is_none_or(|condition| condition.evaluate(self)))
+            .cloned();
+        let Some(trade) = trade else {
+            return Err(GameError::Refused(npc.refusal.clone()
+                .unwrap_or_else(|| format!("{} doesn't want the {}.", npc.title(), self.item_name(&item_id)))));
+        };
+        
+        let title = npc.title();
+        let mut output = self.apply_actions(&[trade.action()]);
+        if output.is_empty() {
+            output.push(format!("{} takes the {}.", title, self.item_name(&item_id)));
+        }
+        self.inventory.remove(&item_id);
+        for given in &trade.gives {
+            output.push(self.hand_to_player(given, &title));
+        }
+        Ok(output.join("\n"))
+    }
+    
+    fn ask(&self, npc_name: Option<&str>, topic: &str) -> Result<String, GameError> {
+        let npc_id = self.choose_npc(npc_name, "ask")?;
+        let npc = &self.story.npcs[&npc_id];
+        match npc.topic_for(topic) {
+            Some(answer) => Ok(answer.to_string()),
+            None => Err(GameError::Refused(format!("{} doesn't know anything about {}.", npc.title(), topic))),
+        }
+    }
+    
+    /// Moves the NPCs whose turn it is: the next room on their patrol, or a
+    /// random neighbouring room they may wander to. Returns what the player
+    /// sees of it.
+    fn move_npcs(&mut self) -> Vec<String> {
+        let mut moving: Vec<String> = self.story.npcs.iter()
+            .filter(|(_, npc)| npc.moves_on(self.turns))
+            .map(|(npc_id, _)| npc_id.clone())
+            .collect();
+        moving.sort();
+        
+        let mut messages = Vec::new();
+        for npc_id in moving {
+            let npc = &self.story.npcs[&npc_id];
+            let from = npc.location.clone();
+            let to = if !npc.patrol.is_empty() {
+                // Starting off the route, the first move goes to its start
+                let step = self.patrol_steps.get(&npc_id).copied()
+                    .or_else(|| npc.patrol.iter().position(|room_id| *room_id == from))
+                    .map_or(0, |step| (step + 1) % npc.patrol.len());
+                // They go through exits like anyone else, and wait at a locked door
+                let to = &npc.patrol[step];
+                let open = self.story.rooms.get(&from)
+                    .is_some_and(|room| room.exits.values().any(|exit| exit.to == *to && !exit.locked));
+                if *to != from && !open {
+                    continue;
+                }
+                self.patrol_steps.insert(npc_id.clone(), step);
+                to.clone()
+            } else {
+                let choices: Vec<&String> = self.story.rooms[&from].exits.values()
+                    .filter(|exit| !exit.locked && npc.wander.contains(&exit.to))
+                    .map(|exit| &exit.to)
+                    .collect();
+                match choices.choose(&mut rand::thread_rng()) {
+                    Some(to) => to.to_string(),
+                    None => continue,
+                }
+            };
+            if to == from {
+                continue;
+            }
+            
+            if from == self.current_room {
+                if self.talking_to.as_ref() == Some(&npc_id) {
+                    self.talking_to = None;
+                }
+                let direction = self.story.rooms[&from].exits.iter()
+                    .filter(|(_, exit)| exit.to == to && !exit.locked)
+                    .map(|(direction, _)| direction)
+                    .min();
+                messages.push(match direction {
+                    Some(direction) => format!("{} leaves {}.", npc.title(), direction),
+                    None => format!("{} leaves.", npc.title()),
+                });
+            } else if to == self.current_room {
+                messages.push(format!("{} arrives.", npc.title()));
+            }
+            if let Some(npc) = self.story.npcs.get_mut(&npc_id) {
+                npc.location = to;
+            }
+        }
+        messages
+    }
+    
+    /// Items the player can refer to: what's in the room, what they carry and
+    /// what's inside open containers among those.
+    fn items_in_reach(&self) -> Result<Vec<String>, GameError> {
+        let mut reach: Vec<String> = self.get_current_room()?.items.iter()
+            .chain(&self.inventory)
+            .cloned()
+            .collect();
+        let inside: Vec<String> = reach.iter()
+            .filter_map(|item_id| self.story.items.get(item_id))
+            .filter(|item| item.container && item.open)
+            .flat_map(|item| item.contents.iter().cloned())
+            .collect();
+        reach.extend(inside);
+        reach.retain(|item_id| self.story.items.contains_key(item_id));
+        Ok(reach)
+    }
+    
+    /// Has `giver` put an item in the player's hands, out of whatever room or
+    /// container had it. If it's more than they can carry it's left in the
+    /// room instead. Returns what the player sees.
+    fn hand_to_player(&mut self, item_id: &str, giver: &str) -> String {
+        let refused = self.refuse_take(item_id).is_some();
+        for room in self.story.rooms.values_mut() {
+            room.items.retain(|id| id != item_id);
+        }
+        for item in self.story.items.values_mut() {
+            item.contents.retain(|id| id != item_id);
+        }
+        
+        if refused {
+            if let Some(room) = self.story.rooms.get_mut(&self.current_room) {
+                room.items.push(item_id.to_string());
+            }
+            format!("{} offers you the {}, but you can't carry it, so it's left here.", giver, self.item_name(item_id))
+        } else {
+            self.inventory.insert(item_id.to_string());
+            format!("{} gives you the {}.", giver, self.item_name(item_id))
+        }
+    }
+    
+    fn enter_room(&mut self, room_id: &str) -> Result<Vec<String>, GameError> {
+        self.current_room = room_id.to_string();
+        *self.visits.entry(room_id.to_string()).or_insert(0) += 1;
+        
+        let actions = self.story.rooms.get(room_id).map(|room| room.on_enter.clone()).unwrap_or_default();
+        let mut messages = self.apply_actions(&actions);
+        messages.extend(self.run_hook("on_enter", (room_id.to_string(),))?.1);
+        Ok(messages)
+    }
+    
+    /// Calls a Lua hook if the story has a script, then copies whatever the
+    /// script changed back into the game. Returns whether the hook handled
+    /// the event and the lines it printed.
+    fn run_hook<'lua, A>(&'lua mut self, hook: &str, args: A) -> Result<(bool, Vec<String>), GameError>
+    where
+        A: IntoLuaMulti<'lua>,
+    {
+        let Some(script) = &self.script else {
+            return Ok((false, Vec::new()));
+        };
+        
+        let mut world = ScriptWorld {
+            current_room: self.current_room.clone(),
+            inventory: self.inventory.clone(),
+            flags: self.flags.clone(),
+            visits: self.visits.clone(),
+            output: Vec::new(),
+        };
+        let handled = script.call_hook(hook, args, &mut world)?;
+        
+        // Items the script gave the player leave whatever room they were in
+        for item_id in world.inventory.difference(&self.inventory) {
+            for room in self.story.rooms.values_mut() {
+                room.items.retain(|id| id != item_id);
+            }
+            for item in self.story.items.values_mut() {
+                item.contents.retain(|id| id != item_id);
+            }
+        }
+        if world.current_room != self.current_room {
+            *world.visits.entry(world.current_room.clone()).or_insert(0) += 1;
+        }
+        
+        self.current_room = world.current_room;
+        self.inventory = world.inventory;
+        self.flags = world.flags;
+        self.visits = world.visits;
+        Ok((handled, world.output))
+    }
+    
+    /// Runs each action whose condition holds and returns the messages to show.
+    fn apply_actions(&mut self, actions: &[Action]) -> Vec<String> {
+        let mut messages = Vec::new();
+        for action in actions {
+            if action.when.as_ref().is_some_and(|condition| !condition.evaluate(self)) {
+                continue;
+            }
+            for (name, value) in &action.set {
+                self.flags.insert(name.clone(), value.clone());
+            }
+            for name in &action.clear {
+                self.flags.remove(name);
+            }
+            if let Some(message) = &action.message {
+                messages.push(message.clone());
+            }
+        }
+        messages
+    }
+    
+    fn flag_is_set(&self, name: &str) -> bool {
+        self.flags.get(name).is_some_and(FlagValue::is_truthy)
+    }
+    
+    fn find_in_inventory(&self, item_name: &str) -> Result<Option<String>, GameError> {
+        self.find_item(&self.inventory, item_name)
+    }
+    
+    /// Works out which of `candidates` the player means by `phrase`. An exact
+    /// name beats a partial one; if several items still fit, the player is asked.
+    fn find_item<'a>(
+        &self,
+        candidates: impl IntoIterator<Item = &'a String>,
+        phrase: &str,
+    ) -> Result<Option<String>, GameError> {
+        let candidates: Vec<&String> = candidates.into_iter().collect();
+        // An `it` or `them` already knows which item it means, even among lookalikes
+        if let Some((name, item_id)) = &self.referent {
+            if phrase.to_lowercase() == *name && candidates.contains(&item_id) {
+                return Ok(Some(item_id.clone()));
+            }
+        }
+        
+        let mut matches: Vec<&String> = candidates.iter()
+            .copied()
+            .filter(|item_id| self.story.items.get(*item_id).is_some_and(|item| item.matches(phrase)))
+            .collect();
+        // Failing whole words, the start of one will do: "take bra"
+        if matches.is_empty() {
+            matches = candidates.into_iter()
+                .filter(|item_id| self.story.items.get(*item_id).is_some_and(|item| item.matches_partly(phrase)))
+                .collect();
+        }
+        if matches.len() > 1 {
+            let exact: Vec<&String> = matches.iter()
+                .copied()
+                .filter(|item_id| self.story.items[*item_id].is_called(phrase))
+                .collect();
+            if exact.len() == 1 {
+                matches = exact;
+            }
+        }
+        
+        // Lookalikes with the same name and adjectives leave the player no
+        // words to tell them apart, so the first of them by ID is taken
+        matches.sort();
+        let kinds: HashSet<(&str, &[String])> = matches.iter()
+            .map(|item_id| {
+                let item = &self.story.items[*item_id];
+                (item.name.as_str(), item.adjectives.as_slice())
+            })
+            .collect();
+        let mut names: Vec<String> = kinds.iter()
+            .map(|&(name, adjectives)| {
+                // Only items sharing a name need their adjectives: "the red key"
+                let shared = kinds.iter().filter(|(other, _)| *other == name).count() > 1;
+                let lower = name.to_lowercase();
+                let extra: Vec<&str> = adjectives.iter()
+                    .map(String::as_str)
+                    .filter(|adjective| shared && !lower.split_whitespace().any(|word| word == adjective.to_lowercase()))
+                    .collect();
+                if extra.is_empty() { name.to_string() } else { format!("{} {}", extra.join(" "), name) }
+            })
+            .collect();
+        names.sort();
+        names.dedup();
+        
+        match (matches.first(), names.len()) {
+            (None, _) => Ok(None),
+            (Some(item_id), 1) => Ok(Some(item_id.to_string())),
+            _ => {
+                let (last, rest) = names.split_last().map_or(("", &[][..]), |(last, rest)| (last.as_str(), rest));
+                let rest: Vec<String> = rest.iter().map(|name| format!("the {}", name)).collect();
+                Err(GameError::Ambiguous {
+                    noun: phrase.to_string(),
+                    question: format!("Which do you mean, {} or the {}?", rest.join(", "), last),
+                })
+            }
+        }
+    }
+    
+    fn item_name(&self, item_id: &str) -> &str {
+        self.story.items.get(item_id).map_or("item", |item| item.name.as_str())
+    }
+    
+    fn item_names(&self, item_ids: &[String]) -> String {
+        item_ids.iter()
+            .map(|item_id| self.item_name(item_id))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+    
+    fn show_inventory(&self) -> Result<String, GameError> {
+        if self.inventory.is_empty() {
+            Ok("Your inventory is empty. :0".to_string())
+        } else {
+            let mut output = "You are carrying:\n".to_string();
+            for item_id in &self.inventory {
+                self.list_item(item_id, 0, &mut output);
+            }
+            if let Some(max_weight) = self.story.config.max_inventory_weight {
+                output.push_str(&format!("Weight: {}/{}\n", self.carried_weight(), max_weight));
+            }
+            // Remove the trailing newline
+            if output.ends_with('\n') {
+                output.pop();
+            }
+            Ok(output)
+        }
+    }
+    
+    /// Adds an inventory line for the item, with the contents of open containers nested underneath.
+    fn list_item(&self, item_id: &str, depth: usize, output: &mut String) {
+        let Some(item) = self.story.items.get(item_id) else {
+            return;
+        };
+        let indent = "  ".repeat(depth);
+        if item.container && !item.open {
+            output.push_str(&format!("{}- {} (closed)\n", indent, item.name));
+            return;
+        }
+        output.push_str(&format!("{}- {}\n", indent, item.name));
+        for inner_id in &item.contents {
+            self.list_item(inner_id, depth + 1, output);
+        }
+    }
+    
+    fn save(&self, slot: &str) -> Result<String, GameError> {
+        let mut saved = self.snapshot();
+        if self.story.config.save_undo_history {
+            saved.undo = self.undo.iter().cloned().collect();
+        }
+        self.saves.write(slot, &saved)?;
+        Ok(format!("Game saved to {}", slot))
+    }
+    
+    fn load(&mut self, slot: &str) -> Result<String, GameError> {
+        self.restore(self.saves.read(slot)?)?;
+        Ok(format!("Game loaded from {}", slot))
+    }
+    
+    fn delete(&self, slot: &str) -> Result<String, GameError> {
+        self.saves.delete(slot)?;
+        Ok(format!("Deleted save {}", slot))
+    }
+    
+    /// Captures everything that has changed since the story started.
+    fn snapshot(&self) -> SavedGame {
+        SavedGame {
+            version: SAVE_VERSION,
+            story: Some(save::fingerprint(&self.story)),
+            current_room: self.current_room.clone(),
+            inventory: self.inventory.clone(),
+            flags: self.flags.clone(),
+            visits: self.visits.clone(),
+            turns: self.turns,
+            rooms: self.story.rooms.iter()
+                .map(|(room_id, room)| (room_id.clone(), RoomState {
+                    items: room.items.clone(),
+                    locked_exits: room.exits.iter()
+                        .filter(|(_, exit)| exit.locked)
+                        .map(|(direction, _)| direction.clone())
+                        .collect(),
+                }))
+                .collect(),
+            containers: self.story.items.iter()
+                .filter(|(_, item)| item.container)
+                .map(|(item_id, item)| (item_id.clone(), ContainerState {
+                    open: item.open,
+                    locked: item.locked,
+                    contents: item.contents.clone(),
+                }))
+                .collect(),
+            npcs: self.story.npcs.iter()
+                .map(|(npc_id, npc)| (npc_id.clone(), NpcState {
+                    location: npc.location.clone(),
+                    patrol_step: self.patrol_steps.get(npc_id).copied(),
+                    dialogue_node: self.dialogue_at.get(npc_id).cloned(),
+                }))
+                .collect(),
+            talking_to: self.talking_to.clone(),
+            saved_at: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs()),
+            playtime: self.earlier_playtime + self.started.elapsed().as_secs(),
+            undo: Vec::new(),
+        }
+    }
+    
+    fn restore(&mut self, mut saved: SavedGame) -> Result<(), GameError> {
+        saved.check_story(&self.story)?;
+        for earlier in &saved.undo {
+            earlier.check_story(&self.story)?;
+        }
+        
+        self.undo = std::mem::take(&mut saved.undo).into();
+        self.redo.clear();
+        self.earlier_playtime = saved.playtime;
+        self.started = Instant::now();
+        self.last_autosave_turn = saved.turns;
+        self.apply_snapshot(saved);
+        Ok(())
+    }
+    
+    /// Puts the world back the way a snapshot describes it.
+    fn apply_snapshot(&mut self, saved: SavedGame) {
+        // Saves from before versioning don't record room contents, so at least
+        // make sure nothing the player carries is still lying around somewhere
+        if saved.rooms.is_empty() {
+            for room in self.story.rooms.values_mut() {
+                room.items.retain(|item_id| !saved.inventory.contains(item_id));
+            }
+        }
+        for (room_id, state) in saved.rooms {
+            if let Some(room) = self.story.rooms.get_mut(&room_id) {
+                room.items = state.items;
+                for (direction, exit) in room.exits.iter_mut() {
+                    exit.locked = state.locked_exits.contains(direction);
+                }
+            }
+        }
+        for (item_id, state) in saved.containers {
+            if let Some(item) = self.story.items.get_mut(&item_id) {
+                item.open = state.open;
+                item.locked = state.locked;
+                item.contents = state.contents;
+            }
+        }
+        // Saves from before NPCs leave them where they are
+        if !saved.npcs.is_empty() {
+            self.patrol_steps.clear();
+            self.dialogue_at.clear();
+        }
+        for (npc_id, state) in saved.npcs {
+            if let Some(npc) = self.story.npcs.get_mut(&npc_id) {
+                npc.location = state.location;
+            }
+            if let Some(step) = state.patrol_step {
+                self.patrol_steps.insert(npc_id.clone(), step);
+            }
+            if let Some(node) = state.dialogue_node {
+                self.dialogue_at.insert(npc_id, node);
+            }
+        }
+        self.talking_to = saved.talking_to;
+        
+        self.current_room = saved.current_room;
+        self.inventory = saved.inventory;
+        self.flags = saved.flags;
+        self.visits = saved.visits;
+        self.turns = saved.turns;
+    }
+    
+    fn undo(&mut self) -> Result<String, GameError> {
+        let Some(before) = self.undo.pop_back() else {
+            return Ok("There's nothing to undo.".to_string());
+        };
+        self.redo.push(self.snapshot());
+        self.apply_snapshot(before);
+        Ok(format!("Undone.{}", self.look()?))
+    }
+    
+    fn redo(&mut self) -> Result<String, GameError> {
+        let Some(after) = self.redo.pop() else {
+            return Ok("There's nothing to redo.".to_string());
+        };
+        self.undo.push_back(self.snapshot());
+        self.apply_snapshot(after);
+        Ok(format!("Redone.{}", self.look()?))
+    }
+    
+    fn list_saves(&self) -> String {
+        match self.saves.list() {
+            Ok(saves) => slots::describe_slots(&self.story, &saves),
+            Err(e) => e.to_string(),
+        }
+    }
+    
+    fn help(&self) -> String {
+        "Available commands:\n- look: Look around the current room\n- examine [thing]: Take a closer look at something (or x [thing])\n- go [direction]: Move in a direction (north, northeast, up, in...)\n- n/s/e/w/ne/nw/se/sw/u/d: Short forms for directions\n- take [item]: Pick up an item\n- drop [item]: Drop an item\n- take all / drop all but [item]: Take or drop everything at once\n- again (g): Repeat the last command\n- it/them: Whatever your last command was about, e.g. 'drop it'\n- unlock [direction or container] with [item]: Unlock something locked\n- open [direction]: Open a locked way with a key you carry\n- open/close [container]: Open or close a chest, bag...\n- take [item] from [container]: Take something out of a container\n- put [item] in [container]: Put something into a container\n- [verb] [item] with [item]: Try something else, e.g. 'cut vines with machete'\n- talk to [someone]: Talk to someone you meet, then pick an answer by its number\n- give [item] to [someone]: Offer someone something you carry\n- ask [someone] about [something]: Ask someone what they know\n- inventory: Check your inventory\n- save [name]: Save the current game to a named slot\n- load [name]: Load a saved game\n- delete [name]: Delete a saved game\n- saves: List available saves\n- undo/redo: Take back your last move, or replay it\n- help: Show this help\n- quit: Exit the game\n\nExample: 'go north' or 'take key then go north' :D".to_string()
+    }
+    
+    fn show_room(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let room = self.get_current_room()?;
+        
+        execute!(
+            io::stdout(),
+            SetForegroundColor(Color::Cyan),
+            Print(format!("{}\n", room.title)),
+            ResetColor
+        )?;
+        
+        execute!(
+            io::stdout(),
+            Print(format!("{}\n", self.describe_room(room, true))),
+        )?;
+        
+        Ok(())
+    }
+    
+    /// Picks the text to show for a room: the first variant whose condition
+    /// holds, then the short description on revisits if `brief` is set, then
+    /// the full description.
+    fn describe_room(&self, room: &Room, brief: bool) -> String {
+        if let Some(variant) = room.variants.iter().find(|variant| variant.when.evaluate(self)) {
+            return variant.text.clone();
+        }
+        
+        match &room.short_description {
+            Some(short) if brief && !self.first_visit() => short.clone(),
+            _ => room.description.clone(),
+        }
+    }
+    
+    fn get_current_room(&self) -> Result<&Room, GameError> {
+        self.story.rooms.get(&self.current_room).ok_or(GameError::InvalidRoom)
+    }
     
     fn clear_screen(&self) -> Result<(), Box<dyn std::error::Error>> {
         execute!(io::stdout(), Clear(ClearType::All))?;
         Ok(())
     }
-}// Basic game loop implemented
-// Inventory system - take/drop items :D
-// Save system with json files
-// Better error messages
+}
+
+/// Reads stdin lines on their own thread so the game loop can also wait for
+/// Ctrl-C. The channel closes at the end of input.
+fn spawn_input_reader() -> mpsc::UnboundedReceiver<String> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        let stdin = io::stdin();
+        loop {
+            let mut line = String::new();
+            match stdin.read_line(&mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+    receiver
+}
+
+impl Context for Game {
+    fn has_item(&self, item_id: &str) -> bool {
+        self.inventory.contains(item_id)
+    }
+    
+    fn item_here(&self, item_id: &str) -> bool {
+        self.get_current_room().is_ok_and(|room| room.items.iter().any(|id| id == item_id))
+    }
+    
+    fn flag(&self, name: &str) -> Option<FlagValue> {
+        self.flags.get(name).cloned()
+    }
+    
+    fn visits(&self, room_id: &str) -> u32 {
+        self.visits.get(room_id).copied().unwrap_or(0)
+    }
+    
+    fn first_visit(&self) -> bool {
+        self.visits(&self.current_room) <= 1
+    }
+    
+    fn is_dark(&self) -> bool {
+        let Ok(room) = self.get_current_room() else {
+            return false;
+        };
+        let gives_light = |item_id: &String| {
+            self.story.items.get(item_id).is_some_and(|item| item.light_source)
+        };
+        room.dark && !self.inventory.iter().any(gives_light) && !room.items.iter().any(gives_light)
+    }
+}// Basic game loop implemented
+// Inventory system - take/drop items :D
+// Save system with json files
+// Better error messages
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fs;
+    use crate::engine::sqlite_store::SqliteSaveStore;
+
+    #[test]
+    fn test_game_creation() {
+        // Create a minimal story for testing
+        let mut rooms = HashMap::new();
+        rooms.insert(
+            "test_room".to_string(), 
+            Room {
+                title: "Test Room".to_string(),
+                description: "A test room".to_string(),
+                exits: HashMap::new(),
+                items: vec!["test_item".to_string()],
+                ..Default::default()
+            }
+        );
+
+        let mut items = HashMap::new();
+        items.insert(
+            "test_item".to_string(),
+            Item {
+                name: "Test Item".to_string(),
+                description: "A test item".to_string(),
+                ..Default::default()
+            }
+        );
+
+        let story = Story {
+            story: StoryInfo {
+                title: "Test Story".to_string(),
+                start_room: "test_room".to_string(),
+                ..Default::default()
+            },
+            rooms,
+            items,
+            ..Default::default()
+        };
+
+        let game = Game::new(story);
+        assert!(game.is_ok());
+    }
+
+    #[test]
+    fn test_take_item() {
+        // Create a story with an item in a room
+        let mut rooms = HashMap::new();
+        let mut exits = HashMap::new();
+        exits.insert("north".to_string(), Exit::new("other_room"));
+        rooms.insert(
+            "start".to_string(), 
+            Room {
+                title: "Start Room".to_string(),
+                description: "A starting room".to_string(),
+                exits,
+                items: vec!["key".to_string()],
+                ..Default::default()
+            }
+        );
+        rooms.insert(
+            "other_room".to_string(), 
+            Room {
+                title: "Other Room".to_string(),
+                description: "Another room".to_string(),
+                exits: HashMap::new(),
+                items: vec![],
+                ..Default::default()
+            }
+        );
+
+        let mut items = HashMap::new();
+        items.insert(
+            "key".to_string(),
+            Item {
+                name: "Brass Key".to_string(),
+                description: "An old brass key".to_string(),
+                ..Default::default()
+            }
+        );
+
+        let story = Story {
+            story: StoryInfo {
+                title: "Test Story".to_string(),
+                start_room: "start".to_string(),
+                ..Default::default()
+            },
+            rooms,
+            items,
+            ..Default::default()
+        };
+
+        let mut game = Game::new(story).unwrap();
+        
+        // Test taking an item that exists
+        let result = game.take("key");
+        assert!(result.is_ok());
+        assert!(result.unwrap().contains("take the"));
+        
+        // Verify the item is now in inventory
+        assert!(game.inventory.contains("key"));
+        
+        // Verify the item is no longer in the room
+        let room = game.story.rooms.get("start").unwrap();
+        assert!(!room.items.contains(&"key".to_string()));
+    }
+
+    #[test]
+    fn test_drop_item() {
+        // Create a story for testing
+        let mut rooms = HashMap::new();
+        rooms.insert(
+            "start".to_string(), 
+            Room {
+                title: "Start Room".to_string(),
+                description: "A starting room".to_string(),
+                exits: HashMap::new(),
+                items: vec![],
+                ..Default::default()
+            }
+        );
+
+        let mut items = HashMap::new();
+        items.insert(
+            "key".to_string(),
+            Item {
+                name: "Brass Key".to_string(),
+                description: "An old brass key".to_string(),
+                ..Default::default()
+            }
+        );
+
+        let story = Story {
+            story: StoryInfo {
+                title: "Test Story".to_string(),
+                start_room: "start".to_string(),
+                ..Default::default()
+            },
+            rooms,
+            items,
+            ..Default::default()
+        };
+
+        let mut game = Game::new(story).unwrap();
+        
+        // Add an item directly to inventory for testing drop
+        game.inventory.insert("key".to_string());
+        
+        // Test dropping an item that exists in inventory
+        let result = game.drop("key");
+        assert!(result.is_ok());
+        assert!(result.unwrap().contains("drop the"));
+        
+        // Verify the item is no longer in inventory
+        assert!(!game.inventory.contains("key"));
+        
+        // Verify the item is now in the room
+        let room = game.story.rooms.get("start").unwrap();
+        assert!(room.items.contains(&"key".to_string()));
+    }
+
+    #[test]
+    fn test_go_command() {
+        // Create a story with connected rooms
+        let mut rooms = HashMap::new();
+        let mut exits = HashMap::new();
+        exits.insert("north".to_string(), Exit::new("north_room"));
+        rooms.insert(
+            "start".to_string(), 
+            Room {
+                title: "Start Room".to_string(),
+                description: "A starting room".to_string(),
+                exits,
+                items: vec![],
+                ..Default::default()
+            }
+        );
+        
+        rooms.insert(
+            "north_room".to_string(), 
+            Room {
+                title: "North Room".to_string(),
+                description: "A room to the north".to_string(),
+                exits: HashMap::new(),
+                items: vec![],
+                ..Default::default()
+            }
+        );
+
+        let items = HashMap::new();
+
+        let story = Story {
+            story: StoryInfo {
+                title: "Test Story".to_string(),
+                start_room: "start".to_string(),
+                ..Default::default()
+            },
+            rooms,
+            items,
+            ..Default::default()
+        };
+
+        let mut game = Game::new(story).unwrap();
+        
+        // Test moving to a connected room
+        let result = game.go("north");
+        assert!(result.is_ok());
+        assert!(result.unwrap().contains("go north"));
+        
+        // Verify the current room has changed
+        assert_eq!(game.current_room, "north_room");
+    }
+
+    #[test]
+    fn test_look_command() {
+        let mut rooms = HashMap::new();
+        let mut exits = HashMap::new();
+        exits.insert("north".to_string(), Exit::new("other_room"));
+        rooms.insert(
+            "start".to_string(), 
+            Room {
+                title: "Start Room".to_string(),
+                description: "A starting room with exits north".to_string(),
+                exits,
+                items: vec!["key".to_string()],
+                ..Default::default()
+            }
+        );
+
+        let mut items = HashMap::new();
+        items.insert(
+            "key".to_string(),
+            Item {
+                name: "Brass Key".to_string(),
+                description: "An old brass key".to_string(),
+                ..Default::default()
+            }
+        );
+
+        let story = Story {
+            story: StoryInfo {
+                title: "Test Story".to_string(),
+                start_room: "start".to_string(),
+                ..Default::default()
+            },
+            rooms,
+            items,
+            ..Default::default()
+        };
+
+        let game = Game::new(story).unwrap();
+        
+        // Test looking at the current room
+        let result = game.look();
+        assert!(result.is_ok());
+        let output = result.unwrap();
+        assert!(output.contains("Start Room"));
+        assert!(output.contains("A starting room with exits north"));
+        assert!(output.contains("Brass Key")); // Should show the item name in the room
+        assert!(output.contains("north")); // Should show the exit
+    }
+
+    #[test]
+    fn test_show_inventory() {
+        let mut rooms = HashMap::new();
+        rooms.insert(
+            "start".to_string(), 
+            Room {
+                title: "Start Room".to_string(),
+                description: "A starting room".to_string(),
+                exits: HashMap::new(),
+                items: vec![],
+                ..Default::default()
+            }
+        );
+
+        let mut items = HashMap::new();
+        items.insert(
+            "key".to_string(),
+            Item {
+                name: "Brass Key".to_string(),
+                description: "An old brass key".to_string(),
+                ..Default::default()
+            }
+        );
+
+        let story = Story {
+            story: StoryInfo {
+                title: "Test Story".to_string(),
+                start_room: "start".to_string(),
+                ..Default::default()
+            },
+            rooms,
+            items,
+            ..Default::default()
+        };
+
+        let mut game = Game::new(story).unwrap();
+        
+        // Test empty inventory
+        let result = game.show_inventory();
+        assert!(result.is_ok());
+        assert!(result.unwrap().contains("empty"));
+        
+        // Add an item to inventory
+        game.inventory.insert("key".to_string());
+        
+        // Test non-empty inventory
+        let result = game.show_inventory();
+        assert!(result.is_ok());
+        let output = result.unwrap();
+        assert!(output.contains("Brass Key"));
+    }
+
+    /// Builds the small worlds the tests play in. Each fixture below spells
+    /// out everything in its world instead of starting from another fixture's.
+    struct StoryBuilder(Story);
+
+    impl StoryBuilder {
+        fn new(start_room: &str) -> Self {
+            Self(Story {
+                story: StoryInfo {
+                    title: "Test Story".to_string(),
+                    start_room: start_room.to_string(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+        }
+
+        fn room(mut self, room_id: &str, title: &str, description: &str) -> Self {
+            self.0.rooms.insert(room_id.to_string(), Room {
+                title: title.to_string(),
+                description: description.to_string(),
+                ..Default::default()
+            });
+            self
+        }
+
+        fn exit(mut self, room_id: &str, direction: &str, exit: Exit) -> Self {
+            self.0.rooms.get_mut(room_id).expect("add the room first").exits.insert(direction.to_string(), exit);
+            self
+        }
+
+        /// Adds an item, lying in `room_id` if there is one.
+        fn item(mut self, item_id: &str, room_id: Option<&str>, item: Item) -> Self {
+            if let Some(room_id) = room_id {
+                self.0.rooms.get_mut(room_id).expect("add the room first").items.push(item_id.to_string());
+            }
+            self.0.items.insert(item_id.to_string(), item);
+            self
+        }
+
+        fn npc(mut self, npc_id: &str, npc: Npc) -> Self {
+            self.0.npcs.insert(npc_id.to_string(), npc);
+            self
+        }
+
+        fn build(self) -> Story {
+            self.0
+        }
+    }
+
+    fn thing(name: &str, description: &str) -> Item {
+        Item {
+            name: name.to_string(),
+            description: description.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn locked_door_story() -> Story {
+        StoryBuilder::new("start")
+            .room("start", "Start Room", "A starting room")
+            .room("vault", "Vault", "A dusty vault")
+            .exit("start", "east", Exit {
+                locked: true,
+                key: Some("key".to_string()),
+                blocked_message: Some("A heavy door blocks the way.".to_string()),
+                ..Exit::new("vault")
+            })
+            .exit("start", "west", Exit {
+                requires_flag: Some("bridge_lowered".to_string()),
+                ..Exit::new("vault")
+            })
+            .item("key", None, thing("Brass Key", "An old brass key"))
+            .item("stick", None, thing("Wooden Stick", "A sturdy stick"))
+            .build()
+    }
+
+    #[test]
+    fn test_locked_exit_blocks_movement() {
+        let mut game = Game::new(locked_door_story()).unwrap();
+
+        let result = game.go("east").unwrap_err().to_string();
+        assert_eq!(result, "A heavy door blocks the way.");
+        assert_eq!(game.current_room, "start");
+        assert!(game.look().unwrap().contains("east (locked)"));
+    }
+
+    #[test]
+    fn test_unlock_exit_with_key() {
+        let mut game = Game::new(locked_door_story()).unwrap();
+        game.inventory.insert("stick".to_string());
+        game.inventory.insert("key".to_string());
+
+        // The wrong item does nothing
+        let result = game.unlock("east", Some("stick")).unwrap_err().to_string();
+        assert!(result.contains("doesn't work"));
+        assert!(game.go("east").unwrap_err().to_string().contains("heavy door"));
+
+        // An item the player doesn't carry is an error
+        assert!(game.unlock("east", Some("lamp")).is_err());
+
+        let result = game.unlock("east", Some("brass")).unwrap();
+        assert!(result.contains("unlock"));
+        assert!(game.go("east").is_ok());
+        assert_eq!(game.current_room, "vault");
+    }
+
+    #[test]
+    fn test_open_exit_with_carried_key() {
+        let mut game = Game::new(locked_door_story()).unwrap();
+
+        // Without the key the door stays shut
+        let result = game.open("east").unwrap_err().to_string();
+        assert!(result.contains("don't have"));
+
+        game.inventory.insert("key".to_string());
+        game.open("east").unwrap();
+        assert!(game.open("east").unwrap_err().to_string().contains("already open"));
+        game.go("east").unwrap();
+        assert_eq!(game.current_room, "vault");
+    }
+
+    #[test]
+    fn test_exit_requires_flag() {
+        let mut game = Game::new(locked_door_story()).unwrap();
+
+        let result = game.go("west").unwrap_err().to_string();
+        assert!(result.contains("stops you"));
+        assert_eq!(game.current_room, "start");
+
+        game.flags.insert("bridge_lowered".to_string(), FlagValue::Bool(true));
+        game.go("west").unwrap();
+        assert_eq!(game.current_room, "vault");
+    }
+
+    /// The locked door world, plus a way back from the vault and flags set
+    /// on the way in.
+    fn flag_story() -> Story {
+        let mut story = locked_door_story();
+        story.rooms.get_mut("vault").unwrap().exits.insert("west".to_string(), Exit::new("start"));
+        story.rooms.get_mut("start").unwrap().items.push("key".to_string());
+        story.items.get_mut("key").unwrap().on_take = vec![Action {
+            set: HashMap::from([("gold".to_string(), FlagValue::Int(3))]),
+            message: Some("The key is warm.".to_string()),
+            ..Default::default()
+        }];
+        story.rooms.get_mut("vault").unwrap().on_enter = vec![
+            Action {
+                when: Some(Condition::parse("visits(vault) == 1").unwrap()),
+                message: Some("Dust swirls as you enter for the first time.".to_string()),
+                set: HashMap::from([("found_vault".to_string(), FlagValue::Bool(true))]),
+                ..Default::default()
+            },
+            Action {
+                set: HashMap::from([("mood".to_string(), FlagValue::Str("curious".to_string()))]),
+                clear: vec!["bridge_lowered".to_string()],
+                ..Default::default()
+            },
+        ];
+        story
+    }
+
+    #[test]
+    fn test_on_enter_actions() {
+        let mut game = Game::new(flag_story()).unwrap();
+        game.flags.insert("bridge_lowered".to_string(), FlagValue::Bool(true));
+
+        let result = game.go("west").unwrap();
+        assert!(result.contains("Dust swirls"));
+        assert!(game.flag_is_set("found_vault"));
+        assert_eq!(game.flags.get("mood"), Some(&FlagValue::Str("curious".to_string())));
+        assert!(!game.flags.contains_key("bridge_lowered"));
+        assert_eq!(game.visits("vault"), 1);
+
+        // The first-visit message only shows once
+        game.go("west").unwrap();
+        game.flags.insert("bridge_lowered".to_string(), FlagValue::Bool(true));
+        let result = game.go("west").unwrap();
+        assert!(!result.contains("Dust swirls"));
+        assert_eq!(game.visits("vault"), 2);
+        assert_eq!(game.visits("start"), 2);
+    }
+
+    #[test]
+    fn test_on_take_actions() {
+        let mut game = Game::new(flag_story()).unwrap();
+
+        let result = game.take("key").unwrap();
+        assert!(result.contains("The key is warm."));
+        assert_eq!(game.flags.get("gold"), Some(&FlagValue::Int(3)));
+    }
+
+    #[test]
+    fn test_exit_condition() {
+        let mut story = flag_story();
+        story.rooms.get_mut("start").unwrap().exits.insert("north".to_string(), Exit {
+            condition: Some(Condition::parse("has(key) && flag.gold >= 3").unwrap()),
+            blocked_message: Some("The gate ignores you.".to_string()),
+            ..Exit::new("vault")
+        });
+        let mut game = Game::new(story).unwrap();
+
+        assert_eq!(game.go("north").unwrap_err().to_string(), "The gate ignores you.");
+        game.take("key").unwrap();
+        game.go("north").unwrap();
+        assert_eq!(game.current_room, "vault");
+    }
+
+    // Keeps test saves out of the real data directory
+    fn game_with_slots(story: Story, dir: &str) -> Game {
+        let mut game = Game::new(story).unwrap();
+        game.set_save_store(Box::new(JsonSaveStore::in_dir(dir)));
+        game
+    }
+
+    #[test]
+    fn test_save_and_load_flags() {
+        let dir = "test_save_and_load_flags";
+        let mut game = game_with_slots(flag_story(), dir);
+        game.take("key").unwrap();
+        game.flags.insert("bridge_lowered".to_string(), FlagValue::Bool(true));
+        game.go("west").unwrap();
+
+        game.save("flags").unwrap();
+
+        let mut restored = game_with_slots(flag_story(), dir);
+        restored.load("flags").unwrap();
+        assert_eq!(restored.current_room, "vault");
+        assert_eq!(restored.flags, game.flags);
+        assert_eq!(restored.visits("vault"), 1);
+
+        // Clean up
+        fs::remove_dir_all(dir).expect("Failed to remove test directory");
+    }
+
+    #[test]
+    fn test_room_description_variants() {
+        let mut story = flag_story();
+        let vault = story.rooms.get_mut("vault").unwrap();
+        vault.dark = true;
+        vault.short_description = Some("The vault.".to_string());
+        vault.variants = vec![
+            DescriptionVariant {
+                when: Condition::parse("dark").unwrap(),
+                text: "It is pitch black.".to_string(),
+            },
+            DescriptionVariant {
+                when: Condition::parse("here(key)").unwrap(),
+                text: "A dusty vault. Something glints on the floor.".to_string(),
+            },
+        ];
+        story.items.get_mut("stick").unwrap().light_source = true;
+        let mut game = Game::new(story).unwrap();
+        game.flags.insert("bridge_lowered".to_string(), FlagValue::Bool(true));
+
+        // Dark without a light source
+        game.go("west").unwrap();
+        assert!(game.look().unwrap().contains("It is pitch black."));
+
+        // Carrying a light shows the full description on the first visit
+        game.inventory.insert("stick".to_string());
+        let room = game.get_current_room().unwrap();
+        assert_eq!(game.describe_room(room, true), "A dusty vault");
+
+        // Revisits get the short description unless the full one is asked for
+        game.go("west").unwrap();
+        game.flags.insert("bridge_lowered".to_string(), FlagValue::Bool(true));
+        game.go("west").unwrap();
+        let room = game.get_current_room().unwrap();
+        assert_eq!(game.describe_room(room, true), "The vault.");
+        assert_eq!(game.describe_room(room, false), "A dusty vault");
+
+        // Dropping the key makes the item variant apply
+        game.inventory.insert("key".to_string());
+        game.drop("key").unwrap();
+        let room = game.get_current_room().unwrap();
+        assert!(game.describe_room(room, true).contains("Something glints"));
+    }
+
+    fn scripted_story(source: &str) -> Story {
+        let mut story = StoryBuilder::new("start")
+            .room("start", "Start Room", "A starting room")
+            .room("vault", "Vault", "A dusty vault")
+            .exit("start", "west", Exit::new("vault"))
+            .exit("vault", "west", Exit::new("start"))
+            .item("key", Some("start"), thing("Brass Key", "An old brass key"))
+            .build();
+        story.script_source = Some(source.to_string());
+        story
+    }
+
+    #[tokio::test]
+    async fn test_script_handles_command() {
+        let mut game = Game::new(scripted_story(r#"
+function on_command(verb, noun)
+    if verb == "pray" then
+        game.give("key")
+        game.move_to("vault")
+        print("You are lifted up.")
+        return true
+    end
+end
+"#)).unwrap();
+
+        let result = game.process_command("pray").await.unwrap();
+        assert_eq!(result, "You are lifted up.");
+        assert_eq!(game.current_room, "vault");
+        assert_eq!(game.visits("vault"), 1);
+        assert!(game.inventory.contains("key"));
+        assert!(!game.story.rooms["start"].items.contains(&"key".to_string()));
+
+        // Commands the script doesn't handle still work
+        let result = game.process_command("inventory").await.unwrap();
+        assert!(result.contains("Brass Key"));
+
+        // Undo takes back what the script did
+        game.process_command("undo").await.unwrap();
+        assert_eq!(game.current_room, "start");
+        assert!(game.inventory.is_empty());
+        assert!(game.story.rooms["start"].items.contains(&"key".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_script_event_hooks() {
+        let mut game = Game::new(scripted_story(r#"
+function on_enter(room) print("Entered " .. room) end
+function on_take(item) game.set_flag("took_" .. item, true) end
+function on_turn(turn) game.set_flag("turns", turn) end
+"#)).unwrap();
+        game.flags.insert("bridge_lowered".to_string(), FlagValue::Bool(true));
+
+        game.process_command("take key").await.unwrap();
+        assert!(game.flag_is_set("took_key"));
+
+        let result = game.process_command("go west").await.unwrap();
+        assert!(result.contains("Entered vault"));
+        assert_eq!(game.flags.get("turns"), Some(&FlagValue::Int(2)));
+
+        // Help doesn't take a turn
+        game.process_command("help").await.unwrap();
+        assert_eq!(game.flags.get("turns"), Some(&FlagValue::Int(2)));
+    }
+
+    #[tokio::test]
+    async fn test_script_error_is_reported() {
+        let mut game = Game::new(scripted_story(r#"
+function on_command(verb, noun)
+    if verb == "dig" then error("the ground is too hard") end
+end
+"#)).unwrap();
+
+        let result = game.process_command("dig").await;
+        assert!(matches!(result, Err(GameError::Script(message)) if message.contains("too hard")));
+    }
+
+    #[tokio::test]
+    async fn test_on_turn_error_keeps_the_turn() {
+        let mut game = Game::new(scripted_story(r#"
+function on_turn(turn)
+    game.set_flag("ticked", true)
+    error("the clock is broken")
+end
+"#)).unwrap();
+
+        // The command still happens and says so, with the error after it
+        let result = game.process_command("take key").await.unwrap();
+        assert!(result.starts_with("You take the Brass Key.\nScript error: "), "{}", result);
+        assert!(result.contains("the clock is broken"));
+        assert!(game.inventory.contains("key"));
+        assert_eq!(game.turns, 1);
+        assert!(!game.flags.contains_key("ticked")); // the failed hook's own changes are dropped
+
+        // And can be taken back
+        game.process_command("undo").await.unwrap();
+        assert!(!game.inventory.contains("key"));
+        assert_eq!(game.turns, 0);
+    }
+
+    #[test]
+    fn test_take_refuses_scenery() {
+        let mut story = flag_story();
+        story.items.get_mut("key").unwrap().portable = false;
+        let mut game = Game::new(story).unwrap();
+
+        let result = game.take("key").unwrap_err().to_string();
+        assert!(result.contains("can't be taken"));
+        assert!(game.inventory.is_empty());
+        assert!(game.story.rooms["start"].items.contains(&"key".to_string()));
+    }
+
+    #[test]
+    fn test_take_enforces_weight_limit() {
+        let mut story = flag_story();
+        story.config.max_inventory_weight = Some(10);
+        story.items.get_mut("key").unwrap().weight = 4;
+        story.items.get_mut("stick").unwrap().weight = 8;
+        story.rooms.get_mut("start").unwrap().items.push("stick".to_string());
+        let mut game = Game::new(story).unwrap();
+
+        game.take("key").unwrap();
+        let result = game.take("stick").unwrap_err().to_string();
+        assert!(result.contains("too heavy"));
+        assert!(result.contains("weighs 8"));
+        assert!(result.contains("6 more"));
+        assert!(!game.inventory.contains("stick"));
+        assert!(game.show_inventory().unwrap().contains("Weight: 4/10"));
+
+        game.drop("key").unwrap();
+        game.take("stick").unwrap();
+        assert!(game.inventory.contains("stick"));
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::collections::HashMap;
+    #[test]
+    fn test_take_enforces_item_count() {
+        let mut story = flag_story();
+        story.config.max_inventory_items = Some(1);
+        story.rooms.get_mut("start").unwrap().items.push("stick".to_string());
+        let mut game = Game::new(story).unwrap();
+
+        game.take("key").unwrap();
+        let result = game.take("stick").unwrap_err().to_string();
+        assert!(result.contains("more than 1"));
+        assert_eq!(game.inventory.len(), 1);
+    }
+
+    fn container_story() -> Story {
+        StoryBuilder::new("start")
+            .room("start", "Start Room", "A starting room")
+            .item("chest", Some("start"), Item {
+                portable: false,
+                container: true,
+                locked: true,
+                key: Some("key".to_string()),
+                contents: vec!["coin".to_string()],
+                ..thing("Oak Chest", "A chest with an iron lock")
+            })
+            .item("bag", Some("start"), Item {
+                weight: 1,
+                container: true,
+                open: true,
+                ..thing("Leather Bag", "A small bag")
+            })
+            .item("coin", None, Item { weight: 2, ..thing("Gold Coin", "A shiny coin") })
+            .item("key", None, thing("Brass Key", "An old brass key"))
+            .item("stick", None, thing("Wooden Stick", "A sturdy stick"))
+            .build()
+    }
 
     #[test]
-    fn test_game_creation() {
-        // Create a minimal story for testing
-        let mut rooms = HashMap::new();
-        rooms.insert(
-            "test_room".to_string(), 
-            Room {
-                title: "Test Room".to_string(),
-                description: "A test room".to_string(),
-                exits: HashMap::new(),
-                items: vec!["test_item".to_string()],
-            }
+    fn test_locked_container() {
+        let mut game = Game::new(container_story()).unwrap();
+
+        assert!(game.open("chest").unwrap_err().to_string().contains("is locked"));
+        assert!(game.take_from("coin", "chest").unwrap_err().to_string().contains("closed"));
+        assert!(game.take("coin").is_err());
+
+        game.inventory.insert("stick".to_string());
+        assert!(game.unlock("chest", Some("stick")).unwrap_err().to_string().contains("doesn't work"));
+
+        game.inventory.insert("key".to_string());
+        let result = game.open("oak chest").unwrap();
+        assert!(result.contains("unlock the Oak Chest"));
+        assert!(result.contains("revealing: Gold Coin"));
+        assert!(game.look().unwrap().contains("In the Oak Chest: Gold Coin"));
+
+        let result = game.take_from("coin", "chest").unwrap();
+        assert_eq!(result, "You take the Gold Coin from the Oak Chest.");
+        assert!(game.inventory.contains("coin"));
+        assert!(game.story.items["chest"].contents.is_empty());
+
+        assert!(game.close("chest").unwrap().contains("close the Oak Chest"));
+        assert!(game.close("chest").unwrap_err().to_string().contains("already closed"));
+    }
+
+    #[test]
+    fn test_take_from_open_container_in_room() {
+        let mut story = container_story();
+        let chest = story.items.get_mut("chest").unwrap();
+        chest.locked = false;
+        chest.open = true;
+        let mut game = Game::new(story).unwrap();
+
+        game.take("coin").unwrap();
+        assert!(game.inventory.contains("coin"));
+        assert!(game.story.items["chest"].contents.is_empty());
+    }
+
+    #[test]
+    fn test_put_in_container() {
+        let mut story = container_story();
+        story.config.max_inventory_weight = Some(3);
+        story.rooms.get_mut("start").unwrap().items.push("coin".to_string());
+        story.items.get_mut("chest").unwrap().contents.clear();
+        let mut game = Game::new(story).unwrap();
+
+        game.take("bag").unwrap();
+        game.take("coin").unwrap();
+        assert!(game.put_in("bag", "bag").unwrap_err().to_string().contains("inside itself"));
+        assert!(game.put_in("coin", "chest").unwrap_err().to_string().contains("closed"));
+
+        let result = game.put_in("coin", "bag").unwrap();
+        assert_eq!(result, "You put the Gold Coin in the Leather Bag.");
+        assert!(!game.inventory.contains("coin"));
+        assert_eq!(game.carried_weight(), 3);
+
+        let inventory = game.show_inventory().unwrap();
+        assert!(inventory.contains("- Leather Bag\n  - Gold Coin"));
+
+        // Taking the coin back out of a carried bag doesn't count against the weight limit
+        game.take_from("coin", "bag").unwrap();
+        assert!(game.inventory.contains("coin"));
+
+        game.close("bag").unwrap();
+        assert!(game.show_inventory().unwrap().contains("Leather Bag (closed)"));
+    }
+
+    #[test]
+    fn test_save_and_load_containers() {
+        let dir = "test_save_and_load_containers";
+        let mut game = game_with_slots(container_story(), dir);
+        game.inventory.insert("key".to_string());
+        game.open("chest").unwrap();
+        game.take_from("coin", "chest").unwrap();
+        game.put_in("coin", "bag").unwrap();
+
+        game.save("containers").unwrap();
+
+        let mut restored = game_with_slots(container_story(), dir);
+        restored.load("containers").unwrap();
+        let chest = &restored.story.items["chest"];
+        assert!(chest.open);
+        assert!(!chest.locked);
+        assert!(chest.contents.is_empty());
+        assert_eq!(restored.story.items["bag"].contents, vec!["coin".to_string()]);
+
+        // Clean up
+        fs::remove_dir_all(dir).expect("Failed to remove test directory");
+    }
+
+    #[test]
+    fn test_save_and_load_world_state() {
+        let dir = "test_save_and_load_world_state";
+        let mut game = game_with_slots(flag_story(), dir);
+        game.inventory.insert("stick".to_string());
+        game.drop("stick").unwrap();
+        game.take("key").unwrap();
+        game.unlock("east", None).unwrap();
+        game.turns = 7;
+
+        game.save("world").unwrap();
+
+        let mut restored = game_with_slots(flag_story(), dir);
+        restored.load("world").unwrap();
+        assert_eq!(restored.story.rooms["start"].items, vec!["stick".to_string()]);
+        assert!(restored.inventory.contains("key"));
+        assert!(!restored.story.rooms["start"].exits["east"].locked);
+        assert_eq!(restored.turns, 7);
+
+        // Clean up
+        fs::remove_dir_all(dir).expect("Failed to remove test directory");
+    }
+
+    #[test]
+    fn test_load_legacy_save() {
+        let dir = "test_load_legacy_save";
+        fs::create_dir_all(dir).expect("Failed to create test directory");
+        fs::write(format!("{}/legacy.save", dir), r#"{"current_room":"start","inventory":["key"]}"#).unwrap();
+
+        let mut game = game_with_slots(flag_story(), dir);
+        game.load("legacy").unwrap();
+        assert!(game.inventory.contains("key"));
+        assert!(!game.story.rooms["start"].items.contains(&"key".to_string()));
+
+        // Clean up
+        fs::remove_dir_all(dir).expect("Failed to remove test directory");
+    }
+
+    #[test]
+    fn test_load_rejects_other_story() {
+        let dir = "test_load_rejects_other_story";
+        let game = game_with_slots(flag_story(), dir);
+        game.save("mine").unwrap();
+
+        let mut other_story = flag_story();
+        other_story.story.title = "Another Story".to_string();
+        let mut other = game_with_slots(other_story, dir);
+        other.take("key").unwrap();
+
+        let result = other.load("mine");
+        assert!(matches!(result, Err(GameError::Save(message)) if message.contains("different story")));
+        assert!(other.inventory.contains("key"));
+
+        // Clean up
+        fs::remove_dir_all(dir).expect("Failed to remove test directory");
+    }
+
+    #[test]
+    fn test_save_slots() {
+        let dir = "test_save_slots";
+        let mut game = game_with_slots(flag_story(), dir);
+        game.turns = 3;
+
+        assert!(game.list_saves().contains("No save files"));
+        assert!(game.save("../../escape").is_err());
+        game.save("before-vault").unwrap();
+
+        let listing = game.list_saves();
+        assert!(listing.contains("before-vault"));
+        assert!(listing.contains("Start Room, turn 3"));
+
+        assert!(game.delete("before-vault").unwrap().contains("Deleted"));
+        assert!(game.load("before-vault").is_err());
+        assert!(game.delete("before-vault").is_err());
+
+        // Clean up
+        fs::remove_dir_all(dir).expect("Failed to remove test directory");
+    }
+
+    #[tokio::test]
+    async fn test_save_commands_on_every_store() {
+        let dir = "test_save_commands_on_every_store";
+        let stores: Vec<Box<dyn SaveStore>> = vec![
+            Box::new(JsonSaveStore::in_dir(dir)),
+            Box::new(SqliteSaveStore::open_in_memory().unwrap()),
+        ];
+
+        for store in stores {
+            let mut game = Game::new(flag_story()).unwrap();
+            game.set_save_store(store);
+
+            game.process_command("take key").await.unwrap();
+            assert_eq!(game.process_command("save first").await.unwrap(), "Game saved to first");
+            game.process_command("drop key").await.unwrap();
+            assert!(game.process_command("saves").await.unwrap().contains("- first ("));
+
+            assert_eq!(game.process_command("load first").await.unwrap(), "Game loaded from first");
+            assert!(game.inventory.contains("key"));
+            assert!(game.story.rooms["start"].items.is_empty());
+
+            assert_eq!(game.process_command("delete first").await.unwrap(), "Deleted save first");
+            assert!(game.process_command("saves").await.unwrap().contains("No save files"));
+            assert!(game.process_command("load first").await.is_err());
+        }
+
+        // Clean up
+        fs::remove_dir_all(dir).expect("Failed to remove test directory");
+    }
+
+    #[tokio::test]
+    async fn test_turn_history() {
+        let mut game = Game::new(flag_story()).unwrap();
+        game.set_save_store(Box::new(SqliteSaveStore::open_in_memory().unwrap()));
+
+        game.process_command("take key").await.unwrap();
+        game.process_command("look").await.unwrap();
+        game.process_command("help").await.unwrap();
+
+        let history = game.saves.history(10).unwrap();
+        let turns: Vec<u32> = history.iter().map(|saved| saved.turns).collect();
+        assert_eq!(turns, vec![2, 1]);
+        assert!(history[1].inventory.contains("key"));
+    }
+
+    /// A store whose disk is always full.
+    struct BrokenStore;
+
+    impl SaveStore for BrokenStore {
+        fn write(&self, _slot: &str, _saved: &SavedGame) -> Result<(), GameError> {
+            Err(GameError::Save("disk full".to_string()))
+        }
+        fn read(&self, _slot: &str) -> Result<SavedGame, GameError> {
+            Err(GameError::Save("disk full".to_string()))
+        }
+        fn delete(&self, _slot: &str) -> Result<(), GameError> {
+            Err(GameError::Save("disk full".to_string()))
+        }
+        fn list(&self) -> Result<Vec<SlotInfo>, GameError> {
+            Ok(Vec::new())
+        }
+        fn location(&self) -> String {
+            "nowhere".to_string()
+        }
+        fn record_turn(&self, _saved: &SavedGame) -> Result<(), GameError> {
+            Err(GameError::Save("disk full".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_turn_history_failure_keeps_the_turn() {
+        let mut game = Game::new(flag_story()).unwrap();
+        game.set_save_store(Box::new(BrokenStore));
+
+        let result = game.process_command("take key").await.unwrap();
+        assert_eq!(result, "You take the Brass Key.\nThe key is warm.\nRecording the turn failed: Couldn't save or load: disk full");
+        assert!(game.inventory.contains("key"));
+    }
+
+    #[tokio::test]
+    async fn test_autosave_rotation() {
+        let mut story = flag_story();
+        story.config.auto_save_turns = Some(2);
+        let mut game = Game::new(story).unwrap();
+        game.set_save_store(Box::new(SqliteSaveStore::open_in_memory().unwrap()));
+        assert!(game.latest_autosave().unwrap().is_none());
+
+        let mut slots = Vec::new();
+        for _ in 0..4 {
+            game.process_command("look").await.unwrap();
+            assert!(!game.autosave_due());
+            game.process_command("look").await.unwrap();
+            assert!(game.autosave_due());
+            slots.push(game.autosave().unwrap());
+        }
+        assert_eq!(slots, vec!["autosave-1", "autosave-2", "autosave-3", "autosave-1"]);
+
+        let latest = game.latest_autosave().unwrap().unwrap();
+        assert_eq!(latest.name, "autosave-1");
+        assert_eq!(latest.saved.turns, 8);
+        assert_eq!(game.saves.list().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_autosave_by_time() {
+        let mut story = flag_story();
+        story.config.auto_save_interval = Some(60);
+        let mut game = Game::new(story).unwrap();
+        assert!(!game.autosave_due());
+
+        game.last_autosave -= std::time::Duration::from_secs(61);
+        assert!(game.autosave_due());
+    }
+
+    #[tokio::test]
+    async fn test_undo_and_redo() {
+        let mut game = Game::new(flag_story()).unwrap();
+        assert!(game.process_command("undo").await.unwrap().contains("nothing to undo"));
+
+        game.process_command("take key").await.unwrap();
+        assert_eq!(game.flags.get("gold"), Some(&FlagValue::Int(3)));
+        game.process_command("drop key").await.unwrap();
+        assert_eq!(game.turns, 2);
+
+        // Undo the drop, then the take
+        let result = game.process_command("undo").await.unwrap();
+        assert!(result.starts_with("Undone."));
+        assert!(game.inventory.contains("key"));
+        assert!(game.story.rooms["start"].items.is_empty());
+        assert_eq!(game.turns, 1);
+
+        game.process_command("undo").await.unwrap();
+        assert!(game.inventory.is_empty());
+        assert_eq!(game.story.rooms["start"].items, vec!["key".to_string()]);
+        assert!(game.flags.is_empty());
+        assert_eq!(game.turns, 0);
+
+        game.process_command("redo").await.unwrap();
+        assert!(game.inventory.contains("key"));
+        assert_eq!(game.flags.get("gold"), Some(&FlagValue::Int(3)));
+
+        // Looking around leaves nothing to undo, and keeps what could be redone
+        game.process_command("look").await.unwrap();
+        game.process_command("inventory").await.unwrap();
+        game.process_command("undo").await.unwrap();
+        assert!(game.inventory.is_empty());
+        game.process_command("redo").await.unwrap();
+
+        // A new move forgets what could be redone
+        game.process_command("undo").await.unwrap();
+        game.process_command("take key").await.unwrap();
+        assert!(game.process_command("redo").await.unwrap().contains("nothing to redo"));
+    }
+
+    #[tokio::test]
+    async fn test_undo_is_bounded() {
+        let mut game = Game::new(flag_story()).unwrap();
+        for _ in 0..UNDO_LIMIT / 2 + 10 {
+            game.process_command("take key").await.unwrap();
+            game.process_command("drop key").await.unwrap();
+        }
+        assert_eq!(game.undo.len(), UNDO_LIMIT);
+    }
+
+    #[tokio::test]
+    async fn test_undo_history_in_saves() {
+        let dir = "test_undo_history_in_saves";
+        let undoable_story = || {
+            let mut story = flag_story();
+            story.config.save_undo_history = true;
+            story
+        };
+        let mut game = game_with_slots(undoable_story(), dir);
+        game.process_command("take key").await.unwrap();
+        game.process_command("save undoable").await.unwrap();
+
+        let mut restored = game_with_slots(undoable_story(), dir);
+        restored.process_command("load undoable").await.unwrap();
+        restored.process_command("undo").await.unwrap();
+        assert!(restored.inventory.is_empty());
+
+        // Without the setting, a loaded game starts with no history
+        let mut plain = game_with_slots(flag_story(), dir);
+        plain.process_command("save plain").await.unwrap();
+        plain.process_command("look").await.unwrap();
+        plain.process_command("load plain").await.unwrap();
+        assert!(plain.process_command("undo").await.unwrap().contains("nothing to undo"));
+
+        // Clean up
+        fs::remove_dir_all(dir).expect("Failed to remove test directory");
+    }
+
+    fn two_keys_story() -> Story {
+        StoryBuilder::new("start")
+            .room("start", "Start Room", "A starting room")
+            .item("key", Some("start"), thing("Brass Key", "An old brass key"))
+            .item("iron_key", Some("start"), Item {
+                adjectives: vec!["heavy".to_string()],
+                ..thing("iron key", "A heavy iron key")
+            })
+            .build()
+    }
+
+    #[test]
+    fn test_take_multi_word_names() {
+        let mut game = Game::new(two_keys_story()).unwrap();
+
+        assert!(game.take("iron key").unwrap().contains("iron key"));
+        assert!(game.inventory.contains("iron_key"));
+        assert!(game.take("heavy key").is_err());
+
+        game.drop("heavy key").unwrap();
+        game.take("brass key").unwrap();
+        assert!(game.inventory.contains("key"));
+    }
+
+    #[tokio::test]
+    async fn test_ambiguous_noun() {
+        let mut game = Game::new(two_keys_story()).unwrap();
+
+        let result = game.process_command("take the key").await;
+        assert!(matches!(
+            &result,
+            Err(GameError::Ambiguous { question, .. }) if question == "Which do you mean, the Brass Key or the iron key?"
+        ));
+        assert!(game.inventory.is_empty());
+
+        // Answering the question finishes the command
+        game.process_command("iron").await.unwrap();
+        assert!(game.inventory.contains("iron_key"));
+        assert!(!game.inventory.contains("key"));
+
+        // Only one key left, so "key" is clear now
+        game.process_command("take key").await.unwrap();
+        assert!(game.inventory.contains("key"));
+        assert!(matches!(game.drop("key"), Err(GameError::Ambiguous { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_same_names_told_apart_by_adjectives() {
+        let key = |adjective: &str| Item {
+            adjectives: vec![adjective.to_string()],
+            ..thing("key", "A small key")
+        };
+        let story = StoryBuilder::new("start")
+            .room("start", "Start Room", "A starting room")
+            .item("red_key", Some("start"), key("red"))
+            .item("blue_key", Some("start"), key("blue"))
+            .item("coin", Some("start"), thing("coin", "A copper coin"))
+            .item("other_coin", Some("start"), thing("coin", "A copper coin"))
+            .build();
+        let mut game = Game::new(story).unwrap();
+
+        assert_eq!(game.process_command("take key").await.unwrap_err().to_string(), "Which do you mean, the blue key or the red key?");
+        game.process_command("red").await.unwrap();
+        assert_eq!(game.inventory, HashSet::from(["red_key".to_string()]));
+
+        // True twins are interchangeable, and the same one is picked every time
+        game.process_command("take coin").await.unwrap();
+        assert!(game.inventory.contains("coin"));
+        game.process_command("drop coin").await.unwrap();
+        game.process_command("take coin").await.unwrap();
+        assert!(game.inventory.contains("coin"));
+    }
+
+    #[test]
+    fn test_exact_name_wins() {
+        let mut story = two_keys_story();
+        story.items.get_mut("iron_key").unwrap().name = "key".to_string();
+        let mut game = Game::new(story).unwrap();
+
+        game.take("key").unwrap();
+        assert!(game.inventory.contains("iron_key"));
+    }
+
+    #[test]
+    fn test_partial_names() {
+        let mut game = Game::new(two_keys_story()).unwrap();
+        assert!(matches!(game.take("ke"), Err(GameError::Ambiguous { .. })));
+        game.take("bra").unwrap();
+        assert!(game.inventory.contains("key"));
+    }
+
+    fn vines_story() -> Story {
+        let reaction = |verb: &str, with: Option<&str>, when: Option<&str>, message: &str| Reaction {
+            verb: verb.to_string(),
+            with: with.map(str::to_string),
+            when: when.map(|source| Condition::parse(source).unwrap()),
+            message: Some(message.to_string()),
+            set: HashMap::from([("vines_cut".to_string(), FlagValue::Bool(true))]),
+            ..Default::default()
+        };
+        StoryBuilder::new("start")
+            .room("start", "Start Room", "A starting room")
+            .item("key", Some("start"), thing("Brass Key", "An old brass key"))
+            .item("vines", Some("start"), Item {
+                portable: false,
+                reactions: vec![
+                    reaction("cut", Some("machete"), Some("!flag.vines_cut"), "You hack the vines apart."),
+                    reaction("cut", Some("machete"), None, "The vines are already cut."),
+                    reaction("take", None, None, "The vines are rooted firmly."),
+                ],
+                ..thing("vines", "Thick, knotted vines.")
+            })
+            .item("machete", Some("start"), thing("rusty machete", "A heavy blade."))
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_item_reactions() {
+        let mut game = Game::new(vines_story()).unwrap();
+
+        assert_eq!(game.process_command("cut the vines with the machete").await.unwrap(), "You hack the vines apart.");
+        assert!(game.flag_is_set("vines_cut"));
+        assert_eq!(game.process_command("cut vines with machete").await.unwrap(), "The vines are already cut.");
+        assert_eq!(game.turns, 2);
+
+        // Reactions can stand in for built-in verbs
+        assert_eq!(game.process_command("take vines").await.unwrap(), "The vines are rooted firmly.");
+
+        // Nothing fits
+        assert_eq!(game.process_command("cut vines").await.unwrap_err().to_string(), "You can't cut the vines.");
+        assert_eq!(game.process_command("burn vines with machete").await.unwrap_err().to_string(), "You can't burn the vines with the rusty machete.");
+        assert_eq!(game.process_command("cut key with vines").await.unwrap_err().to_string(), "You can't cut the Brass Key with the vines.");
+        assert_eq!(game.process_command("cut rope with machete").await.unwrap_err().to_string(), "You don't see any rope here.");
+    }
+
+    #[tokio::test]
+    async fn test_script_sees_phrase() {
+        let mut game = Game::new(scripted_story(r#"
+function on_command(verb, object, preposition, indirect)
+    if verb == "give" then
+        game.set_flag("gift", object .. "|" .. preposition .. "|" .. indirect)
+        return true
+    end
+end
+"#)).unwrap();
+
+        game.process_command("give the bread to the old man").await.unwrap();
+        assert_eq!(game.flags.get("gift"), Some(&FlagValue::Str("bread|to|old man".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_story_verbs() {
+        let mut story = vines_story();
+        story.verbs.insert("pray".to_string(), Verb {
+            aliases: vec!["kneel".to_string()],
+            message: Some("Nothing happens.".to_string()),
+        });
+        story.verbs.insert("cut".to_string(), Verb { aliases: vec!["chop".to_string()], message: None });
+        story.verbs.insert("take".to_string(), Verb { aliases: vec!["grab".to_string()], message: None });
+        story.rooms.get_mut("start").unwrap().reactions = vec![Reaction {
+            verb: "pray".to_string(),
+            message: Some("A warm breeze answers.".to_string()),
+            ..Default::default()
+        }];
+        story.items.insert("stick".to_string(), thing("Wooden Stick", "A sturdy stick"));
+        story.rooms.insert("vault".to_string(), Room { items: vec!["stick".to_string()], ..Default::default() });
+        let mut game = Game::new(story).unwrap();
+
+        // Bare story verbs, and their aliases, reach room reactions
+        assert_eq!(game.process_command("kneel").await.unwrap(), "A warm breeze answers.");
+        assert_eq!(game.process_command("chop the vines with the machete").await.unwrap(), "You hack the vines apart.");
+
+        // Aliases of built-in verbs work like the verb
+        game.process_command("grab key").await.unwrap();
+        assert!(game.inventory.contains("key"));
+
+        // With nothing to react, the verb's own message is used
+        game.current_room = "vault".to_string();
+        assert_eq!(game.process_command("pray").await.unwrap_err().to_string(), "Nothing happens.");
+        assert_eq!(game.process_command("pray to stick").await.unwrap_err().to_string(), "Nothing happens.");
+        assert!(game.process_command("dance").await.unwrap_err().to_string().contains("don't understand"));
+    }
+
+    #[tokio::test]
+    async fn test_named_exits() {
+        let mut story = flag_story();
+        let vault = story.rooms.get_mut("vault").unwrap();
+        vault.exits.insert("back".to_string(), Exit::new("start"));
+        vault.exits.insert("trapdoor".to_string(), Exit::new("start"));
+        story.directions.insert("back".to_string(), DirectionDef { aliases: vec!["return".to_string()], opposite: None });
+        let mut game = Game::new(story).unwrap();
+
+        assert_eq!(game.process_command("back").await.unwrap_err().to_string(), "You can't go back from here.");
+
+        game.current_room = "vault".to_string();
+        game.process_command("back").await.unwrap();
+        assert_eq!(game.current_room, "start");
+
+        game.current_room = "vault".to_string();
+        game.process_command("return").await.unwrap();
+        assert_eq!(game.current_room, "start");
+
+        game.current_room = "vault".to_string();
+        game.process_command("go return").await.unwrap();
+        assert_eq!(game.current_room, "start");
+
+        // Exit names work bare even when the story doesn't declare them
+        game.current_room = "vault".to_string();
+        game.process_command("trapdoor").await.unwrap();
+        assert_eq!(game.current_room, "start");
+    }
+
+    #[tokio::test]
+    async fn test_directions() {
+        let mut story = locked_door_story();
+        let vault = story.rooms.get_mut("vault").unwrap();
+        vault.exits.insert("up".to_string(), Exit::new("start"));
+        vault.exits.insert("sw".to_string(), Exit::new("start"));
+        vault.exits.insert("inside".to_string(), Exit::new("start"));
+        story.rooms.get_mut("start").unwrap().items.push("key".to_string());
+        let mut game = Game::new(story).unwrap();
+
+        // Abbreviations reach exits written out in full, and the other way round
+        game.process_command("take key").await.unwrap();
+        assert_eq!(game.process_command("unlock e").await.unwrap(), "You unlock the way east with the Brass Key.");
+        assert_eq!(game.process_command("e").await.unwrap(), "You go east.");
+        assert_eq!(game.process_command("u").await.unwrap(), "You go up.");
+        game.current_room = "vault".to_string();
+        assert_eq!(game.process_command("go southwest").await.unwrap(), "You go southwest.");
+        game.current_room = "vault".to_string();
+        assert_eq!(game.process_command("inside").await.unwrap(), "You go in.");
+        game.current_room = "vault".to_string();
+        assert_eq!(game.process_command("d").await.unwrap_err().to_string(), "You can't go down from here.");
+        assert_eq!(game.process_command("go north").await.unwrap_err().to_string(), "You can't go north from here.");
+    }
+
+    #[tokio::test]
+    async fn test_story_directions() {
+        let mut story = flag_story();
+        let vault = story.rooms.get_mut("vault").unwrap();
+        vault.exits.insert("back".to_string(), Exit::new("start"));
+        story.directions.insert("back".to_string(), DirectionDef {
+            aliases: vec!["out".to_string()],
+            opposite: Some("onward".to_string()),
+        });
+        let mut game = Game::new(story).unwrap();
+
+        assert_eq!(game.directions.opposite("back"), Some("onward"));
+        assert_eq!(game.directions.opposite("onward"), Some("back"));
+        assert_eq!(game.directions.opposite("ne"), Some("southwest"));
+
+        // The story's alias wins over the built-in `out`
+        game.current_room = "vault".to_string();
+        assert_eq!(game.process_command("out").await.unwrap(), "You go back.");
+        assert_eq!(game.current_room, "start");
+    }
+
+    fn npc_story() -> Story {
+        StoryBuilder::new("start")
+            .room("start", "Start Room", "A starting room")
+            .room("vault", "Vault", "A dusty vault")
+            .exit("start", "west", Exit::new("vault"))
+            .exit("vault", "west", Exit::new("start"))
+            .item("key", Some("start"), thing("Brass Key", "An old brass key"))
+            .npc("guard", Npc {
+                name: "guard".to_string(),
+                description: "A bored guard.".to_string(),
+                location: "start".to_string(),
+                synonyms: vec!["sentry".to_string()],
+                adjectives: vec!["bored".to_string()],
+                talk: Some("\"Move along.\"".to_string()),
+                patrol: vec!["start".to_string(), "vault".to_string()],
+                every: 2,
+                ..Default::default()
+            })
+            .npc("cat", Npc {
+                name: "cat".to_string(),
+                description: "A grey cat.".to_string(),
+                location: "vault".to_string(),
+                wander: vec!["start".to_string(), "vault".to_string()],
+                ..Default::default()
+            })
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_npcs() {
+        let mut game = Game::new(npc_story()).unwrap();
+        let look = game.look().unwrap();
+        assert!(look.contains("Guard is here."));
+        assert!(!look.contains("Cat"));
+
+        assert_eq!(game.process_command("x sentry").await.unwrap(), "A bored guard.\nCat arrives.");
+        assert_eq!(
+            game.process_command("talk to the bored guard").await.unwrap(),
+            "\"Move along.\"\nCat leaves west.\nGuard leaves west."
         );
+        assert_eq!(game.story.npcs["guard"].location, "vault");
+        assert_eq!(game.process_command("talk to guard").await.unwrap_err().to_string(), "There's no one called guard here.");
+        assert_eq!(game.process_command("talk to key").await.unwrap_err().to_string(), "The Brass Key doesn't answer.");
 
-        let mut items = HashMap::new();
-        items.insert(
-            "test_item".to_string(),
-            Item {
-                name: "Test Item".to_string(),
-                description: "A test item".to_string(),
-            }
+        // The patrol starts over at the end of its route
+        game.process_command("look").await.unwrap();
+        assert_eq!(game.process_command("inventory").await.unwrap(), "Your inventory is empty. :0\nCat leaves west.\nGuard arrives.");
+        assert_eq!(game.story.npcs["guard"].location, "start");
+
+        // NPCs with nothing to say say so
+        game.story.npcs.get_mut("guard").unwrap().talk = None;
+        assert!(game.process_command("talk to guard").await.unwrap().starts_with("Guard has nothing to say."));
+
+        // Patrols wait at locked doors
+        game.story.npcs.get_mut("guard").unwrap().location = "start".to_string();
+        game.story.rooms.get_mut("start").unwrap().exits.get_mut("west").unwrap().locked = true;
+        for _ in 0..4 {
+            game.process_command("look").await.unwrap();
+        }
+        assert_eq!(game.story.npcs["guard"].location, "start");
+    }
+
+    #[tokio::test]
+    async fn test_npcs_are_saved() {
+        let mut game = Game::new(npc_story()).unwrap();
+        game.process_command("look").await.unwrap();
+        game.process_command("look").await.unwrap();
+        let saved = SavedGame::from_json(&game.snapshot().to_json().unwrap()).unwrap();
+        assert_eq!(saved.npcs["guard"].location, "vault");
+        assert_eq!(saved.npcs["guard"].patrol_step, Some(1));
+
+        game.process_command("look").await.unwrap();
+        game.process_command("look").await.unwrap();
+        assert_eq!(game.story.npcs["guard"].location, "start");
+        game.apply_snapshot(saved);
+        assert_eq!(game.story.npcs["guard"].location, "vault");
+        assert_eq!(game.patrol_steps["guard"], 1);
+
+        // Undo puts them back too
+        game.process_command("look").await.unwrap();
+        game.process_command("look").await.unwrap();
+        assert_eq!(game.story.npcs["guard"].location, "start");
+        game.process_command("undo").await.unwrap();
+        assert_eq!(game.story.npcs["guard"].location, "vault");
+    }
+
+    fn ghost_dialogue() -> HashMap<String, DialogueNode> {
+        toml::from_str(r#"
+[start]
+text = "Have you seen my book?"
+choices = [
+    { text = "What book?", goto = "book" },
+    { text = "Is this it?", when = "has(key)", take = ["key"], set = { ghost_helped = true }, goto = "thanks" },
+    { text = "Goodbye." },
+]
+
+[book]
+text = "My diary. I lost it somewhere in this house."
+choices = [{ text = "I'll keep an eye out." }]
+
+[thanks]
+text = "Thank you! Take this."
+choices = [{ text = "You're welcome.", give = ["stick"], goto = "farewell", end = true }]
+
+[farewell]
+text = "She fades away."
+next = "rest"
+choices = [{ text = "Wait!" }]
+
+[rest]
+text = "The ghost smiles peacefully."
+next = "rest"
+"#).unwrap()
+    }
+
+    fn ghost_story() -> Story {
+        StoryBuilder::new("start")
+            .room("start", "Start Room", "A starting room")
+            .item("key", Some("start"), thing("Brass Key", "An old brass key"))
+            .item("stick", None, thing("Wooden Stick", "A sturdy stick"))
+            .npc("ghost", Npc {
+                name: "ghost".to_string(),
+                description: "A pale ghost.".to_string(),
+                location: "start".to_string(),
+                dialogue: ghost_dialogue(),
+                ..Default::default()
+            })
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_dialogue() {
+        let mut game = Game::new(ghost_story()).unwrap();
+        assert_eq!(game.process_command("1").await.unwrap_err().to_string(), "You're not talking to anyone.");
+
+        // Choices whose condition doesn't hold aren't offered
+        assert_eq!(game.process_command("talk to ghost").await.unwrap(), "Have you seen my book?\n1. What book?\n2. Goodbye.");
+        assert_eq!(game.process_command("3").await.unwrap_err().to_string(), "Choose an answer from 1 to 2.");
+        assert_eq!(game.process_command("1").await.unwrap(), "My diary. I lost it somewhere in this house.\n1. I'll keep an eye out.");
+
+        // Doing something else walks away, and talking again picks up where it left off
+        game.process_command("take key").await.unwrap();
+        assert_eq!(game.process_command("1").await.unwrap_err().to_string(), "You're not talking to anyone.");
+        assert_eq!(game.process_command("talk to ghost").await.unwrap(), "My diary. I lost it somewhere in this house.\n1. I'll keep an eye out.");
+        assert_eq!(game.process_command("1").await.unwrap(), "\"I'll keep an eye out.\"");
+        assert!(game.talking_to.is_none());
+
+        assert_eq!(
+            game.process_command("talk to ghost").await.unwrap(),
+            "Have you seen my book?\n1. What book?\n2. Is this it?\n3. Goodbye."
+        );
+        assert_eq!(game.process_command("2").await.unwrap(), "You hand over the Brass Key.\nThank you! Take this.\n1. You're welcome.");
+        assert!(game.flag_is_set("ghost_helped"));
+        assert!(!game.inventory.contains("key"));
+
+        // `end` shows the node without its choices, and `next` is where the next conversation starts
+        assert_eq!(game.process_command("1").await.unwrap(), "Ghost gives you the Wooden Stick.\nShe fades away.");
+        assert!(game.inventory.contains("stick"));
+        assert_eq!(game.process_command("1").await.unwrap_err().to_string(), "You're not talking to anyone.");
+        assert_eq!(game.process_command("talk to ghost").await.unwrap(), "The ghost smiles peacefully.");
+        assert_eq!(game.process_command("talk to ghost").await.unwrap(), "The ghost smiles peacefully.");
+
+        // Answers that hand something over need it in hand, even without a `when`
+        let mut story = ghost_story();
+        story.npcs.get_mut("ghost").unwrap().dialogue.get_mut("start").unwrap().choices[1].when = None;
+        let mut game = Game::new(story).unwrap();
+        assert_eq!(game.process_command("talk to ghost").await.unwrap(), "Have you seen my book?\n1. What book?\n2. Goodbye.");
+
+        // Someone who walks off ends the conversation
+        game.story.npcs.get_mut("ghost").unwrap().location = "vault".to_string();
+        assert_eq!(game.process_command("1").await.unwrap_err().to_string(), "You're not talking to anyone.");
+        assert!(game.talking_to.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_dialogue_is_saved() {
+        let mut game = Game::new(ghost_story()).unwrap();
+        game.process_command("talk to ghost").await.unwrap();
+        game.process_command("1").await.unwrap();
+        let saved = SavedGame::from_json(&game.snapshot().to_json().unwrap()).unwrap();
+        assert_eq!(saved.talking_to.as_deref(), Some("ghost"));
+        assert_eq!(saved.npcs["ghost"].dialogue_node.as_deref(), Some("book"));
+
+        game.process_command("1").await.unwrap();
+        game.apply_snapshot(saved);
+        assert_eq!(game.process_command("1").await.unwrap(), "\"I'll keep an eye out.\"");
+        assert_eq!(game.dialogue_at["ghost"], "start");
+
+        // Undo takes back an answer
+        game.process_command("talk to ghost").await.unwrap();
+        game.process_command("1").await.unwrap();
+        game.process_command("undo").await.unwrap();
+        assert_eq!(game.process_command("2").await.unwrap(), "\"Goodbye.\"");
+    }
+
+    fn trader_story() -> Story {
+        StoryBuilder::new("start")
+            .room("start", "Start Room", "A starting room")
+            .item("key", Some("start"), Item {
+                on_take: vec![Action {
+                    set: HashMap::from([("gold".to_string(), FlagValue::Int(3))]),
+                    ..Default::default()
+                }],
+                ..thing("Brass Key", "An old brass key")
+            })
+            .item("stick", None, thing("Wooden Stick", "A sturdy stick"))
+            .item("candle", Some("start"), thing("Candle", "A stub of wax."))
+            .npc("ghost", Npc {
+                name: "ghost".to_string(),
+                description: "A pale ghost.".to_string(),
+                location: "start".to_string(),
+                refusal: Some("The ghost's hand passes right through it.".to_string()),
+                topics: HashMap::from([("old diary".to_string(), "\"It has all our names in it.\"".to_string())]),
+                trades: vec![
+                    Trade {
+                        wants: "key".to_string(),
+                        when: Some(Condition::parse("flag.gold > 5").unwrap()),
+                        message: Some("\"Riches won't help me now.\"".to_string()),
+                        ..Default::default()
+                    },
+                    Trade {
+                        wants: "key".to_string(),
+                        gives: vec!["stick".to_string()],
+                        set: HashMap::from([("ghost_helped".to_string(), FlagValue::Bool(true))]),
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            })
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_give_and_ask() {
+        let mut game = Game::new(trader_story()).unwrap();
+        assert!(matches!(game.process_command("give key to ghost").await, Err(GameError::ItemNotFound)));
+        assert_eq!(game.process_command("ask ghost about diary").await.unwrap(), "\"It has all our names in it.\"");
+        assert_eq!(game.process_command("ask about the weather").await.unwrap_err().to_string(), "Ghost doesn't know anything about weather.");
+        assert_eq!(game.process_command("ask guard about diary").await.unwrap_err().to_string(), "There's no one called guard here.");
+
+        game.process_command("take key").await.unwrap();
+        game.process_command("take candle").await.unwrap();
+        assert_eq!(game.process_command("give candle to ghost").await.unwrap_err().to_string(), "The ghost's hand passes right through it.");
+        assert!(game.inventory.contains("candle"));
+
+        // The first trade that wants the item and whose condition holds is the one that happens
+        let turns = game.turns;
+        assert_eq!(game.process_command("give key").await.unwrap(), "Ghost takes the Brass Key.\nGhost gives you the Wooden Stick.");
+        assert_eq!(game.turns, turns + 1);
+        assert!(game.flag_is_set("ghost_helped"));
+        assert!(!game.inventory.contains("key"));
+        assert!(game.inventory.contains("stick"));
+
+        game.story.npcs.get_mut("ghost").unwrap().location = "vault".to_string();
+        assert_eq!(game.process_command("give candle").await.unwrap_err().to_string(), "There's no one here to give the Candle to.");
+        assert_eq!(game.process_command("ask about diary").await.unwrap_err().to_string(), "There's no one here to ask.");
+
+        // What the player can't carry is left in the room
+        let mut story = trader_story();
+        story.config.max_inventory_weight = Some(3);
+        story.items.get_mut("stick").unwrap().weight = 5;
+        let mut game = Game::new(story).unwrap();
+        game.process_command("take key").await.unwrap();
+        assert_eq!(
+            game.process_command("give key").await.unwrap(),
+            "Ghost takes the Brass Key.\nGhost offers you the Wooden Stick, but you can't carry it, so it's left here."
         );
+        assert!(game.inventory.is_empty());
+        assert!(game.story.rooms["start"].items.contains(&"stick".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_examine() {
+        let mut story = container_story();
+        story.rooms.get_mut("start").unwrap().scenery = HashMap::from([
+            ("portraits".to_string(), "Stern faces watch you.".to_string()),
+        ]);
+        story.rooms.get_mut("start").unwrap().items.push("key".to_string());
+        let mut game = Game::new(story).unwrap();
+
+        assert_eq!(game.process_command("examine key").await.unwrap(), "An old brass key");
+        assert_eq!(game.process_command("x chest").await.unwrap(), "A chest with an iron lock\nThe Oak Chest is closed.");
+        assert_eq!(game.process_command("look at the bag").await.unwrap(), "A small bag\nThe Leather Bag is empty.");
+
+        // Carried items and what's in open containers can be examined too
+        game.process_command("take key").await.unwrap();
+        game.process_command("open chest").await.unwrap();
+        assert_eq!(game.process_command("examine brass key").await.unwrap(), "An old brass key");
+        assert_eq!(game.process_command("x chest").await.unwrap(), "A chest with an iron lock\nIn the Oak Chest: Gold Coin");
+        assert_eq!(game.process_command("x coin").await.unwrap(), "A shiny coin");
 
-        let story = Story {
-            story: StoryInfo {
-                title: "Test Story".to_string(),
-                start_room: "test_room".to_string(),
-            },
-            rooms,
-            items,
-        };
+        // Scenery can be examined but not taken
+        assert_eq!(game.process_command("x portraits").await.unwrap(), "Stern faces watch you.");
+        assert_eq!(game.process_command("take portraits").await.unwrap_err().to_string(), "You can't take the portraits.");
+        assert_eq!(game.process_command("burn portraits").await.unwrap_err().to_string(), "You can't burn the portraits.");
+        assert_eq!(game.inventory.len(), 1);
 
-        let game = Game::new(story);
-        assert!(game.is_ok());
+        assert_eq!(game.process_command("examine dragon").await.unwrap_err().to_string(), "You don't see any dragon here.");
+
+        // Nothing can be made out in the dark, not even what you're holding
+        game.story.rooms.get_mut("start").unwrap().dark = true;
+        assert_eq!(game.process_command("x key").await.unwrap_err().to_string(), "It's too dark to see anything.");
+        assert_eq!(game.process_command("x portraits").await.unwrap_err().to_string(), "It's too dark to see anything.");
     }
 
-    #[test]
-    fn test_take_item() {
-        // Create a story with an item in a room
-        let mut rooms = HashMap::new();
-        let mut exits = HashMap::new();
-        exits.insert("north".to_string(), "other_room".to_string());
-        rooms.insert(
-            "start".to_string(), 
-            Room {
-                title: "Start Room".to_string(),
-                description: "A starting room".to_string(),
-                exits,
-                items: vec!["key".to_string()],
-            }
-        );
-        rooms.insert(
-            "other_room".to_string(), 
-            Room {
-                title: "Other Room".to_string(),
-                description: "Another room".to_string(),
-                exits: HashMap::new(),
-                items: vec![],
-            }
-        );
+    #[tokio::test]
+    async fn test_command_chains() {
+        let mut game = Game::new(flag_story()).unwrap();
+        game.flags.insert("bridge_lowered".to_string(), FlagValue::Bool(true));
 
-        let mut items = HashMap::new();
-        items.insert(
-            "key".to_string(),
-            Item {
-                name: "Brass Key".to_string(),
-                description: "An old brass key".to_string(),
-            }
-        );
+        let result = game.process_command("take key. go west then drop key").await.unwrap();
+        assert!(result.starts_with("You take the Brass Key."));
+        assert!(result.ends_with("You drop the Brass Key."));
+        assert_eq!(game.current_room, "vault");
+        assert_eq!(game.turns, 3);
 
-        let story = Story {
-            story: StoryInfo {
-                title: "Test Story".to_string(),
-                start_room: "start".to_string(),
-            },
-            rooms,
-            items,
-        };
+        // The first failure stops the rest, but earlier commands still count
+        let result = game.process_command("take key. go north. drop key").await.unwrap();
+        assert!(result.ends_with("You can't go north from here."));
+        assert!(game.inventory.contains("key"));
+        assert_eq!(game.turns, 4);
 
-        let mut game = Game::new(story).unwrap();
-        
-        // Test taking an item that exists
-        let result = game.take("key");
-        assert!(result.is_ok());
-        assert!(result.unwrap().contains("take the"));
-        
-        // Verify the item is now in inventory
+        // If the very first command fails, that's the error
+        assert!(matches!(game.process_command("go north. drop key").await, Err(GameError::Refused(_))));
         assert!(game.inventory.contains("key"));
-        
-        // Verify the item is no longer in the room
-        let room = game.story.rooms.get("start").unwrap();
-        assert!(!room.items.contains(&"key".to_string()));
     }
 
-    #[test]
-    fn test_drop_item() {
-        // Create a story for testing
-        let mut rooms = HashMap::new();
-        rooms.insert(
-            "start".to_string(), 
-            Room {
-                title: "Start Room".to_string(),
-                description: "A starting room".to_string(),
-                exits: HashMap::new(),
-                items: vec![],
-            }
-        );
+    #[tokio::test]
+    async fn test_refused_commands_take_no_turn() {
+        let mut game = Game::new(scripted_story(r#"
+function on_command(verb, object, preposition, indirect)
+    if verb == "go" and object == "north" then
+        game.set_flag("tried_north", true)
+    end
+end
+"#)).unwrap();
 
-        let mut items = HashMap::new();
-        items.insert(
-            "key".to_string(),
-            Item {
-                name: "Brass Key".to_string(),
-                description: "An old brass key".to_string(),
-            }
-        );
+        // Nothing happened, so there's no turn and nothing to undo
+        game.process_command("take key").await.unwrap();
+        assert!(game.process_command("go south").await.is_err());
+        assert_eq!(game.turns, 1);
+        assert_eq!(game.undo.len(), 1);
 
-        let story = Story {
-            story: StoryInfo {
-                title: "Test Story".to_string(),
-                start_room: "start".to_string(),
-            },
-            rooms,
-            items,
-        };
+        // But what the script did before the refusal is a turn, and undo takes it back
+        assert_eq!(game.process_command("go north").await.unwrap_err().to_string(), "You can't go north from here.");
+        assert!(game.flag_is_set("tried_north"));
+        assert_eq!(game.turns, 2);
+        game.process_command("undo").await.unwrap();
+        assert!(!game.flag_is_set("tried_north"));
+        assert!(game.inventory.contains("key"));
+    }
 
-        let mut game = Game::new(story).unwrap();
-        
-        // Add an item directly to inventory for testing drop
-        game.inventory.insert("key".to_string());
-        
-        // Test dropping an item that exists in inventory
-        let result = game.drop("key");
-        assert!(result.is_ok());
-        assert!(result.unwrap().contains("drop the"));
-        
-        // Verify the item is no longer in inventory
-        assert!(!game.inventory.contains("key"));
-        
-        // Verify the item is now in the room
-        let room = game.story.rooms.get("start").unwrap();
-        assert!(room.items.contains(&"key".to_string()));
+    #[tokio::test]
+    async fn test_again() {
+        let mut game = Game::new(container_story()).unwrap();
+        assert_eq!(game.process_command("g").await.unwrap_err().to_string(), "There's nothing to repeat.");
+
+        game.process_command("close bag").await.unwrap();
+        assert_eq!(game.process_command("again").await.unwrap_err().to_string(), "The Leather Bag is already closed.");
+
+        let result = game.process_command("open bag. g").await.unwrap();
+        assert_eq!(result, "You open the Leather Bag. It's empty.\nThe Leather Bag is already open.");
+        assert!(game.story.items["bag"].open);
     }
 
-    #[test]
-    fn test_go_command() {
-        // Create a story with connected rooms
-        let mut rooms = HashMap::new();
-        let mut exits = HashMap::new();
-        exits.insert("north".to_string(), "north_room".to_string());
-        rooms.insert(
-            "start".to_string(), 
-            Room {
-                title: "Start Room".to_string(),
-                description: "A starting room".to_string(),
-                exits,
-                items: vec![],
-            }
-        );
-        
-        rooms.insert(
-            "north_room".to_string(), 
-            Room {
-                title: "North Room".to_string(),
-                description: "A room to the north".to_string(),
-                exits: HashMap::new(),
-                items: vec![],
-            }
+    #[tokio::test]
+    async fn test_take_and_drop_all() {
+        let mut story = two_keys_story();
+        story.items.insert("stick".to_string(), thing("Wooden Stick", "A sturdy stick"));
+        story.rooms.get_mut("start").unwrap().items.push("stick".to_string());
+        story.items.insert(
+            "statue".to_string(),
+            Item { name: "statue".to_string(), portable: false, ..Default::default() }
         );
+        story.rooms.get_mut("start").unwrap().items.push("statue".to_string());
+        let mut game = Game::new(story).unwrap();
 
-        let items = HashMap::new();
+        // Scenery is left where it is
+        let result = game.process_command("take all but the stick").await.unwrap();
+        assert_eq!(result, "You take the Brass Key.\nYou take the iron key.");
+        assert!(game.inventory.contains("key") && game.inventory.contains("iron_key"));
 
-        let story = Story {
-            story: StoryInfo {
-                title: "Test Story".to_string(),
-                start_room: "start".to_string(),
-            },
-            rooms,
-            items,
-        };
+        game.process_command("take everything").await.unwrap();
+        assert_eq!(game.inventory.len(), 3);
+        assert_eq!(game.process_command("take all").await.unwrap_err().to_string(), "There's nothing here to take.");
+
+        let result = game.process_command("drop all except brass key").await.unwrap();
+        assert_eq!(result, "You drop the Wooden Stick.\nYou drop the iron key.");
+        assert_eq!(game.inventory, HashSet::from(["key".to_string()]));
+
+        game.process_command("drop all").await.unwrap();
+        assert_eq!(game.process_command("drop all").await.unwrap_err().to_string(), "You don't have anything to drop.");
+    }
+
+    #[tokio::test]
+    async fn test_pronouns() {
+        let mut game = Game::new(two_keys_story()).unwrap();
+        assert_eq!(game.process_command("drop it").await.unwrap_err().to_string(), "I'm not sure what you're referring to.");
+
+        game.process_command("take iron key").await.unwrap();
+        assert_eq!(game.process_command("examine it").await.unwrap(), "A heavy iron key");
+        game.process_command("drop it").await.unwrap();
+        assert!(game.inventory.is_empty());
+
+        // "them" after taking several things
+        game.process_command("take all").await.unwrap();
+        let result = game.process_command("drop them").await.unwrap();
+        assert_eq!(result, "You drop the Brass Key.\nYou drop the iron key.");
+        assert!(game.inventory.is_empty());
+
+        // `it` is one thing only
+        game.process_command("take all").await.unwrap();
+        assert_eq!(game.process_command("drop it").await.unwrap_err().to_string(), "I'm not sure what you're referring to.");
+        game.process_command("drop them").await.unwrap();
 
+        // Pronouns follow the chain as it runs
+        game.process_command("take brass key then drop it. take it").await.unwrap();
+        assert_eq!(game.inventory, HashSet::from(["key".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn test_pronouns_mean_the_same_item() {
+        let mut story = two_keys_story();
+        for (item_id, adjective, description) in [("gold_coin", "gold", "A gold coin."), ("fake_coin", "lead", "A painted lead coin.")] {
+            story.items.insert(item_id.to_string(), Item {
+                adjectives: vec![adjective.to_string()],
+                ..thing("coin", description)
+            });
+            story.rooms.get_mut("start").unwrap().items.push(item_id.to_string());
+        }
         let mut game = Game::new(story).unwrap();
-        
-        // Test moving to a connected room
-        let result = game.go("north");
-        assert!(result.is_ok());
-        assert!(result.unwrap().contains("go north"));
-        
-        // Verify the current room has changed
-        assert_eq!(game.current_room, "north_room");
+
+        // Both coins are in reach, but `it` is the one just taken
+        game.process_command("take gold coin").await.unwrap();
+        assert_eq!(game.process_command("examine it").await.unwrap(), "A gold coin.");
+        game.process_command("drop it. take lead coin").await.unwrap();
+        assert_eq!(game.process_command("x it").await.unwrap(), "A painted lead coin.");
     }
 
-    #[test]
-    fn test_look_command() {
-        let mut rooms = HashMap::new();
-        let mut exits = HashMap::new();
-        exits.insert("north".to_string(), "other_room".to_string());
-        rooms.insert(
-            "start".to_string(), 
-            Room {
-                title: "Start Room".to_string(),
-                description: "A starting room with exits north".to_string(),
-                exits,
-                items: vec!["key".to_string()],
-            }
-        );
+    #[tokio::test]
+    async fn test_typo_suggestions() {
+        let mut game = Game::new(flag_story()).unwrap();
 
-        let mut items = HashMap::new();
-        items.insert(
-            "key".to_string(),
-            Item {
-                name: "Brass Key".to_string(),
-                description: "An old brass key".to_string(),
-            }
-        );
+        assert_eq!(game.process_command("tkae key").await.unwrap_err().to_string(), "Did you mean \"take key\"?");
+        assert_eq!(game.process_command("take the brss kye").await.unwrap_err().to_string(), "Did you mean \"take the brass key\"?");
+        assert_eq!(game.process_command("go esat").await.unwrap_err().to_string(), "Did you mean \"go east\"?");
+        assert!(game.inventory.is_empty());
+        assert_eq!(game.turns, 0);
 
-        let story = Story {
-            story: StoryInfo {
-                title: "Test Story".to_string(),
-                start_room: "start".to_string(),
-            },
-            rooms,
-            items,
-        };
+        // Nothing close enough
+        assert!(game.process_command("xyzzy").await.unwrap_err().to_string().contains("don't understand"));
+    }
 
-        let game = Game::new(story).unwrap();
-        
-        // Test looking at the current room
-        let result = game.look();
-        assert!(result.is_ok());
-        let output = result.unwrap();
-        assert!(output.contains("Start Room"));
-        assert!(output.contains("A starting room with exits north"));
-        assert!(output.contains("Brass Key")); // Should show the item name in the room
-        assert!(output.contains("north")); // Should show the exit
+    #[tokio::test]
+    async fn test_typo_correction_modes() {
+        let mut story = flag_story();
+        story.config.typo_correction = TypoCorrection::Correct;
+        let mut game = Game::new(story).unwrap();
+
+        let result = game.process_command("tkae key").await.unwrap();
+        assert_eq!(result, "(taking that as \"take key\")\nYou take the Brass Key.\nThe key is warm.");
+        assert!(game.inventory.contains("key"));
+
+        let mut story = flag_story();
+        story.config.typo_correction = TypoCorrection::Off;
+        let mut game = Game::new(story).unwrap();
+        assert_eq!(game.process_command("tkae key").await.unwrap_err().to_string(), "You can't tkae the Brass Key.");
     }
 
-    #[test]
-    fn test_show_inventory() {
-        let mut rooms = HashMap::new();
-        rooms.insert(
-            "start".to_string(), 
-            Room {
-                title: "Start Room".to_string(),
-                description: "A starting room".to_string(),
-                exits: HashMap::new(),
-                items: vec![],
-            }
-        );
+    #[tokio::test]
+    async fn test_typo_correction_runs_scripts_once() {
+        let mut story = scripted_story(r#"
+function on_command(verb, object, preposition, indirect)
+    game.set_flag("commands", (game.get_flag("commands") or 0) + 1)
+end
+"#);
+        story.config.typo_correction = TypoCorrection::Correct;
+        let mut game = Game::new(story).unwrap();
 
-        let mut items = HashMap::new();
-        items.insert(
-            "key".to_string(),
-            Item {
-                name: "Brass Key".to_string(),
-                description: "An old brass key".to_string(),
-            }
-        );
+        game.process_command("tkae key").await.unwrap();
+        assert_eq!(game.flags.get("commands"), Some(&FlagValue::Int(1)));
 
-        let story = Story {
-            story: StoryInfo {
-                title: "Test Story".to_string(),
-                start_room: "start".to_string(),
-            },
-            rooms,
-            items,
-        };
+        // Save names are left as they are
+        game.story.config.typo_correction = TypoCorrection::Suggest;
+        assert!(game.process_command("delete kye").await.unwrap_err().to_string().contains("kye"));
+    }
 
+    #[tokio::test]
+    async fn test_typo_correction_leaves_answers_alone() {
+        let mut story = StoryBuilder::new("start")
+            .room("start", "Start Room", "A starting room")
+            .item("red_key", Some("start"), thing("red key", "A red key"))
+            .item("blue_key", Some("start"), thing("blue key", "A blue key"))
+            .build();
+        story.config.typo_correction = TypoCorrection::Correct;
         let mut game = Game::new(story).unwrap();
-        
-        // Test empty inventory
-        let result = game.show_inventory();
-        assert!(result.is_ok());
-        assert!(result.unwrap().contains("empty"));
-        
-        // Add an item to inventory
-        game.inventory.insert("key".to_string());
-        
-        // Test non-empty inventory
-        let result = game.show_inventory();
-        assert!(result.is_ok());
-        let output = result.unwrap();
-        assert!(output.contains("Brass Key"));
+
+        // "red" is close to "redo", but here it answers the question
+        assert!(game.process_command("take key").await.unwrap_err().to_string().starts_with("Which do you mean"));
+        assert_eq!(game.process_command("red").await.unwrap(), "You take the red key.");
+        assert!(game.inventory.contains("red_key"));
+
+        game.story.config.typo_correction = TypoCorrection::Suggest;
+        game.process_command("drop red key").await.unwrap();
+        assert!(game.process_command("take key").await.unwrap_err().to_string().starts_with("Which do you mean"));
+        assert_eq!(game.process_command("blue").await.unwrap(), "You take the blue key.");
     }
-}
\ No newline at end of file
+}
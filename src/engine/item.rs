@@ -1,9 +1,122 @@
+use crate::engine::action::{Action, Reaction};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Item {
     pub name: String,
     pub description: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub synonyms: Vec<String>, // other nouns the player may use, e.g. "lantern" for a lamp
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub adjectives: Vec<String>, // extra words that tell similar items apart
+    #[serde(default)]
+    pub weight: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default = "default_portable")]
+    pub portable: bool, // scenery can't be picked up
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on_take: Vec<Action>, // run each time the player picks the item up
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub light_source: bool, // lets the player see in dark rooms
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub container: bool, // chests, bags, drawers...
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub open: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub locked: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>, // item ID that unlocks this container
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub contents: Vec<String>, // item IDs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reactions: Vec<Reaction>, // story responses to verbs like "cut vines with machete"
+}
+
+fn default_portable() -> bool {
+    true
+}
+
+impl Item {
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+    
+    /// Whether the player could mean this item by `phrase`, see `fits`.
+    pub fn matches(&self, phrase: &str) -> bool {
+        fits(&self.vocabulary(), phrase)
+    }
+    
+    /// Like `matches`, but words may be cut short: "bra" for "brass key".
+    pub fn matches_partly(&self, phrase: &str) -> bool {
+        fits_partly(&self.vocabulary(), phrase)
+    }
+    
+    /// Whether `phrase` is exactly this item's name or one of its synonyms.
+    pub fn is_called(&self, phrase: &str) -> bool {
+        let phrase = phrase.trim().to_lowercase();
+        std::iter::once(&self.name)
+            .chain(&self.synonyms)
+            .any(|name| name.to_lowercase() == phrase)
+    }
+    
+    /// Every word the player can use for this item.
+    pub fn vocabulary(&self) -> Vec<String> {
+        vocabulary(&self.name, &self.synonyms, &self.adjectives)
+    }
+}
+
+/// Every word of a name, its synonyms and adjectives, lowercased. Items and
+/// NPCs are both referred to this way.
+pub fn vocabulary(name: &str, synonyms: &[String], adjectives: &[String]) -> Vec<String> {
+    std::iter::once(name)
+        .chain(synonyms.iter().map(String::as_str))
+        .chain(adjectives.iter().map(String::as_str))
+        .flat_map(str::split_whitespace)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Whether every word of `phrase` is in `vocabulary`, so "key", "brass key"
+/// and "small shiny key" can all refer to a "brass key".
+pub fn fits(vocabulary: &[String], phrase: &str) -> bool {
+    fits_with(vocabulary, phrase, |known, word| known == word)
+}
+
+/// Whether every word of `phrase` starts a word in `vocabulary`.
+pub fn fits_partly(vocabulary: &[String], phrase: &str) -> bool {
+    fits_with(vocabulary, phrase, |known, word| known.starts_with(word))
+}
+
+fn fits_with(vocabulary: &[String], phrase: &str, fit: impl Fn(&str, &str) -> bool) -> bool {
+    let phrase = phrase.to_lowercase();
+    let mut words = phrase.split_whitespace().peekable();
+    if words.peek().is_none() {
+        return false;
+    }
+    words.all(|word| vocabulary.iter().any(|known| fit(known, word)))
+}
+
+impl Default for Item {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            description: String::new(),
+            synonyms: Vec::new(),
+            adjectives: Vec::new(),
+            weight: 0,
+            tags: Vec::new(),
+            portable: default_portable(),
+            on_take: Vec::new(),
+            light_source: false,
+            container: false,
+            open: false,
+            locked: false,
+            key: None,
+            contents: Vec::new(),
+            reactions: Vec::new(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -15,9 +128,79 @@ mod tests {
         let item = Item {
             name: "Test Item".to_string(),
             description: "A test item".to_string(),
+            ..Default::default()
         };
 
         assert_eq!(item.name, "Test Item");
         assert_eq!(item.description, "A test item");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_item_properties() {
+        let item: Item = toml::from_str(r#"
+name = "Iridescent Seashell"
+description = "A beautiful shell."
+weight = 1
+tags = ["collectible", "shiny"]
+"#).unwrap();
+
+        assert_eq!(item.weight, 1);
+        assert!(item.has_tag("shiny"));
+        assert!(!item.has_tag("heavy"));
+        assert!(item.portable);
+
+        let statue: Item = toml::from_str(r#"
+name = "stone statue"
+description = "A carved figure."
+portable = false
+"#).unwrap();
+
+        assert!(!statue.portable);
+        assert_eq!(statue.weight, 0);
+        assert!(statue.tags.is_empty());
+    }
+
+    #[test]
+    fn test_container_item() {
+        let chest: Item = toml::from_str(r#"
+name = "wooden chest"
+description = "A heavy chest bound with iron."
+portable = false
+container = true
+locked = true
+key = "rusty_key"
+contents = ["gold_coin", "map"]
+"#).unwrap();
+
+        assert!(chest.container);
+        assert!(!chest.open);
+        assert!(chest.locked);
+        assert_eq!(chest.key.as_deref(), Some("rusty_key"));
+        assert_eq!(chest.contents, vec!["gold_coin".to_string(), "map".to_string()]);
+    }
+
+    #[test]
+    fn test_item_matches_noun_phrases() {
+        let key: Item = toml::from_str(r#"
+name = "Brass Key"
+description = "A small brass key."
+synonyms = ["latchkey"]
+adjectives = ["small", "shiny"]
+"#).unwrap();
+
+        assert!(key.matches("key"));
+        assert!(key.matches("brass key"));
+        assert!(key.matches("Small Shiny Key"));
+        assert!(key.matches("latchkey"));
+        assert!(!key.matches("iron key"));
+        assert!(!key.matches("bra"));
+        assert!(key.matches_partly("bra"));
+        assert!(key.matches_partly("shiny br"));
+        assert!(!key.matches_partly("iron"));
+        assert!(!key.matches(""));
+
+        assert!(key.is_called("brass key"));
+        assert!(key.is_called("latchkey"));
+        assert!(!key.is_called("key"));
+    }
+}
@@ -1,4 +1,15 @@
 pub mod game;
 pub mod room;
+pub mod exit;
+pub mod direction;
 pub mod item;
-pub mod parser;
\ No newline at end of file
+pub mod npc;
+pub mod dialogue;
+pub mod parser;
+pub mod spelling;
+pub mod condition;
+pub mod action;
+pub mod script;
+pub mod save;
+pub mod slots;
+pub mod sqlite_store;
\ No newline at end of file
@@ -0,0 +1,213 @@
+use crate::engine::{action::Action, condition::{Condition, FlagValue}, dialogue::DialogueNode, item, room};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Someone the player can meet. NPCs can stay put, walk a fixed route or
+/// wander between rooms, moving every `every` turns:
+///
+/// ```toml
+/// [npcs.guard]
+/// name = "guard"
+/// description = "A bored guard in a dented helmet."
+/// location = "gate"
+/// patrol = ["gate", "courtyard", "tower", "courtyard"]
+/// every = 2
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Npc {
+    pub name: String,
+    pub description: String,
+    pub location: String, // room ID, updated as the NPC moves
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub synonyms: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub adjectives: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub talk: Option<String>, // the reply to `talk to` when there's no dialogue
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub dialogue: HashMap<String, DialogueNode>, // node ID -> node, see `DialogueNode`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub topics: HashMap<String, String>, // what `ask <npc> about <topic>` gets
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trades: Vec<Trade>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refusal: Option<String>, // the reply to being given something no trade wants
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patrol: Vec<String>, // room IDs visited in order, starting over at the end
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wander: Vec<String>, // room IDs to drift between at random, through exits
+    #[serde(default = "default_every")]
+    pub every: u32, // turns between moves
+}
+
+fn default_every() -> u32 {
+    1
+}
+
+impl Npc {
+    /// The name as it starts a sentence: "Old man leaves north."
+    pub fn title(&self) -> String {
+        let mut chars = self.name.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        }
+    }
+
+    /// Whether the player could mean this NPC by `phrase`, like `Item::matches`.
+    pub fn matches(&self, phrase: &str) -> bool {
+        item::fits(&self.vocabulary(), phrase)
+    }
+
+    /// Every word the player can use for this NPC.
+    pub fn vocabulary(&self) -> Vec<String> {
+        item::vocabulary(&self.name, &self.synonyms, &self.adjectives)
+    }
+
+    /// The answer to being asked about `topic`, found like scenery.
+    pub fn topic_for(&self, topic: &str) -> Option<&str> {
+        room::find_noun(&self.topics, topic).map(|(_, answer)| answer)
+    }
+
+    /// Whether this turn is one the NPC moves on.
+    pub fn moves_on(&self, turn: u32) -> bool {
+        (!self.patrol.is_empty() || !self.wander.is_empty()) && self.every > 0 && turn.is_multiple_of(self.every)
+    }
+}
+
+/// What an NPC does when given a particular item: hands something back,
+/// sets flags, or both. The NPC keeps the item.
+///
+/// ```toml
+/// [[npcs.keeper.trades]]
+/// wants = "fish"
+/// gives = ["lamp_oil"]
+/// message = "\"Fresh! Here, for the lamp.\""
+/// set = { keeper_fed = true }
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Trade {
+    pub wants: String, // item ID
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub gives: Vec<String>, // item IDs
+    // The rest is an `Action`, spelled out like `Reaction` does
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<Condition>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub set: HashMap<String, FlagValue>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clear: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl Trade {
+    pub fn action(&self) -> Action {
+        Action {
+            when: self.when.clone(),
+            set: self.set.clone(),
+            clear: self.clear.clone(),
+            message: self.message.clone(),
+        }
+    }
+}
+
+impl Default for Npc {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            description: String::new(),
+            location: String::new(),
+            synonyms: Vec::new(),
+            adjectives: Vec::new(),
+            talk: None,
+            dialogue: HashMap::new(),
+            topics: HashMap::new(),
+            trades: Vec::new(),
+            refusal: None,
+            patrol: Vec::new(),
+            wander: Vec::new(),
+            every: default_every(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topics_and_trades() {
+        let npc: Npc = toml::from_str(r#"
+name = "Mira"
+description = "The lighthouse keeper."
+location = "lamp_room"
+refusal = "Mira shakes her head."
+topics = { lighthouse = "\"Built by my grandfather.\"", "lamp oil" = "\"We're nearly out.\"" }
+
+[[trades]]
+wants = "fish"
+gives = ["oil"]
+when = "!flag.keeper_fed"
+message = "\"Fresh! Here, for the lamp.\""
+set = { keeper_fed = true }
+"#).unwrap();
+
+        assert_eq!(npc.topic_for("LIGHTHOUSE"), Some("\"Built by my grandfather.\""));
+        assert_eq!(npc.topic_for("oil"), Some("\"We're nearly out.\""));
+        assert_eq!(npc.topic_for("weather"), None);
+
+        let trade = &npc.trades[0];
+        assert_eq!(trade.wants, "fish");
+        assert_eq!(trade.gives, ["oil"]);
+        assert_eq!(trade.action().set["keeper_fed"], FlagValue::Bool(true));
+        assert_eq!(npc.refusal.as_deref(), Some("Mira shakes her head."));
+    }
+
+    #[test]
+    fn test_npc_from_toml() {
+        let npc: Npc = toml::from_str(r#"
+name = "old man"
+description = "A weathered fisherman mending a net."
+location = "shore"
+synonyms = ["fisherman"]
+adjectives = ["weathered"]
+patrol = ["shore", "pier"]
+every = 3
+"#).unwrap();
+
+        assert_eq!(npc.title(), "Old man");
+        assert!(npc.matches("old man"));
+        assert!(npc.matches("weathered fisherman"));
+        assert!(!npc.matches("young man"));
+        assert!(!npc.matches(""));
+
+        assert!(npc.moves_on(3));
+        assert!(npc.moves_on(6));
+        assert!(!npc.moves_on(4));
+
+        let still: Npc = toml::from_str(r#"
+name = "Mira"
+description = "The lighthouse keeper."
+location = "lamp_room"
+"#).unwrap();
+        assert_eq!(still.every, 1);
+        assert!(!still.moves_on(1));
+
+        assert!(toml::from_str::<Npc>(r#"
+name = "Mira"
+description = "The lighthouse keeper."
+location = "lamp_room"
+trades = [{ wants = "fish", give = ["oil"] }]
+"#).is_err());
+
+        assert!(toml::from_str::<Npc>(r#"
+name = "Mira"
+description = "The lighthouse keeper."
+location = "lamp_room"
+patrole = ["lamp_room"]
+"#).is_err());
+    }
+}
@@ -1,32 +1,207 @@
-#[derive(Debug)]
+use crate::engine::direction;
+
+#[derive(Debug, Clone)]
 pub enum Command {
     Look,
+    Examine(String),
     Go(String),
-    North,
-    South,
-    East,
-    West,
     Take(String),
+    TakeAll(Vec<String>), // except these
+    TakeFrom(String, String), // item, container
+    PutIn(String, String), // item, container
     Drop(String),
+    DropAll(Vec<String>),
+    Unlock(String, Option<String>), // direction or container, item used as the key
+    Open(String),
+    Close(String),
+    Talk(String), // NPC
+    Choose(usize), // a numbered answer in a conversation
+    Give(String, Option<String>), // item, NPC
+    Ask(Option<String>, String), // NPC, topic
     Inventory,
-    Save(String),
+    Save(String), // slot name
     Load(String),
+    Delete(String),
     ListSaves,
+    Undo,
+    Redo,
     Help,
+    Again,
+    Do(Phrase), // anything else with an object, for the story to react to
     Unknown,
 }
 
+/// A command split into its grammatical parts, e.g. "cut the vines with the
+/// machete" is verb `cut`, object `vines`, preposition `with`, indirect `machete`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Phrase {
+    pub verb: String,
+    pub object: Option<String>,
+    pub preposition: Option<String>,
+    pub indirect: Option<String>,
+}
+
+const PREPOSITIONS: [&str; 11] = ["with", "on", "onto", "in", "into", "inside", "to", "at", "from", "under", "about"];
+
+pub const DEFAULT_SLOT: &str = "quicksave";
+
+// Saves used to be files in the working directory, so people still type `save game.save`
+fn slot_name(token: &str) -> String {
+    token.strip_suffix(".save").unwrap_or(token).to_string()
+}
+
+const ARTICLES: [&str; 4] = ["the", "a", "an", "some"];
+
+/// Joins the words naming a thing, leaving out articles: "the brass key" -> "brass key".
+fn noun_phrase(tokens: &[&str]) -> String {
+    tokens.iter()
+        .filter(|token| !ARTICLES.contains(&token.to_lowercase().as_str()))
+        .copied()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether a command is about everything: `all` or `all but lamp and key`.
+/// Returns the exceptions.
+fn all_except(tokens: &[&str]) -> Option<Vec<String>> {
+    let (first, rest) = tokens.split_first()?;
+    if !matches!(first.to_lowercase().as_str(), "all" | "everything") {
+        return None;
+    }
+    let Some((but, rest)) = rest.split_first() else {
+        return Some(Vec::new());
+    };
+    if !matches!(but.to_lowercase().as_str(), "but" | "except") {
+        return None;
+    }
+    Some(rest.split(|t| t.eq_ignore_ascii_case("and") || *t == ",")
+        .filter_map(object)
+        .collect())
+}
+
+/// Like `noun_phrase`, but `None` if nothing is left.
+fn object(tokens: &[&str]) -> Option<String> {
+    Some(noun_phrase(tokens)).filter(|phrase| !phrase.is_empty())
+}
+
+/// Splits a command at its first preposition: `give bread to old man` is
+/// `give` + `bread` + `to` + `old man`. `None` for empty input.
+pub fn parse_phrase(input: &str) -> Option<Phrase> {
+    let tokens: Vec<&str> = input.split_whitespace().collect();
+    let (verb, rest) = tokens.split_first()?;
+    
+    let mut phrase = Phrase { verb: verb.to_lowercase(), ..Default::default() };
+    match rest.iter().position(|t| PREPOSITIONS.contains(&t.to_lowercase().as_str())) {
+        Some(i) => {
+            phrase.object = object(&rest[..i]);
+            phrase.preposition = Some(rest[i].to_lowercase());
+            phrase.indirect = object(&rest[i + 1..]);
+        }
+        None => phrase.object = object(rest),
+    }
+    Some(phrase)
+}
+
+/// Splits a line into separate commands at full stops and `then`:
+/// "take key. go north then drop key" is three commands.
+pub fn split_commands(input: &str) -> Vec<String> {
+    // Only a full stop followed by a space or the end counts, so "save game.save" survives
+    let mut sentences = Vec::new();
+    let mut current = String::new();
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '.' && chars.peek().is_none_or(|next| next.is_whitespace()) {
+            sentences.push(std::mem::take(&mut current));
+        } else {
+            current.push(c);
+        }
+    }
+    sentences.push(current);
+    
+    let mut commands = Vec::new();
+    for sentence in sentences {
+        let mut command: Vec<&str> = Vec::new();
+        for word in sentence.split_whitespace() {
+            if word.eq_ignore_ascii_case("then") {
+                commands.push(command.join(" "));
+                command.clear();
+            } else {
+                command.push(word);
+            }
+        }
+        commands.push(command.join(" "));
+    }
+    commands.retain(|command| !command.is_empty());
+    commands
+}
+
+const PRONOUNS: [&str; 2] = ["it", "them"];
+
+/// Every built-in verb, for spelling suggestions. Keep in step with `parse_command`.
+pub const VERBS: [&str; 35] = [
+    "look", "examine", "inspect", "go", "take", "get", "pick", "drop", "talk", "speak", "give",
+    "offer", "hand", "ask", "put", "place", "insert", "unlock", "open", "close", "shut", "inventory", "inv",
+    "save", "load", "delete", "saves", "list", "again", "undo", "redo", "help", "l", "x", "i",
+];
+
+/// Whether `word` is part of the grammar rather than naming something:
+/// articles, prepositions, pronouns and the like.
+pub fn is_function_word(word: &str) -> bool {
+    let word = word.to_lowercase();
+    ARTICLES.contains(&word.as_str())
+        || PREPOSITIONS.contains(&word.as_str())
+        || PRONOUNS.contains(&word.as_str())
+        || ["all", "everything", "but", "except", "and", "up", "then"].contains(&word.as_str())
+}
+
+/// Fills in `it` and `them` with the things they stand for, one command per
+/// thing: "drop them" with a key and a lamp is "drop key" and "drop lamp".
+/// `it` only stands for one thing, so with several there's no command.
+/// `None` if the command has no pronoun.
+pub fn replace_pronouns(input: &str, nouns: &[String]) -> Option<Vec<String>> {
+    let is_pronoun = |word: &str| PRONOUNS.contains(&word.to_lowercase().as_str());
+    if !input.split_whitespace().any(is_pronoun) {
+        return None;
+    }
+    if nouns.len() > 1 && input.split_whitespace().any(|word| word.eq_ignore_ascii_case("it")) {
+        return Some(Vec::new());
+    }
+    
+    Some(nouns.iter()
+        .map(|noun| {
+            input.split_whitespace()
+                .map(|word| if is_pronoun(word) { noun.as_str() } else { word })
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect())
+}
+
 pub fn parse_command(input: &str) -> Command {
-    let tokens: Vec<&str> = input.trim().split_whitespace().collect();
+    let tokens: Vec<&str> = input.split_whitespace().collect();
     
     if tokens.is_empty() {
         return Command::Unknown;
     }
     
+    // A direction on its own, "n" or "up", or a number picking an answer
+    if let [word] = tokens[..] {
+        if let Some(direction) = direction::built_in(word) {
+            return Command::Go(direction.to_string());
+        }
+        if let Ok(number) = word.parse() {
+            return Command::Choose(number);
+        }
+    }
+    
     let cmd = tokens[0].to_lowercase();
     
     match cmd.as_str() {
-        "look" | "l" | "examine" | "x" => Command::Look,
+        "look" | "l" if tokens.get(1).is_some_and(|t| t.eq_ignore_ascii_case("at")) => {
+            object(&tokens[2..]).map_or(Command::Look, Command::Examine)
+        },
+        "look" | "l" => Command::Look,
+        "examine" | "x" | "inspect" => object(&tokens[1..]).map_or(Command::Look, Command::Examine),
         "go" => {
             if tokens.len() > 1 {
                 Command::Go(tokens[1].to_string())
@@ -34,47 +209,98 @@ pub fn parse_command(input: &str) -> Command {
                 Command::Unknown
             }
         },
-        "n" | "north" => Command::North,
-        "s" | "south" => Command::South,
-        "e" | "east" => Command::East,
-        "w" | "west" => Command::West,
         "take" | "get" | "pick" => {
-            if tokens.len() > 1 {
-                // Handle "pick up" as a special case
-                if cmd == "pick" && tokens.len() > 2 && tokens[1] == "up" {
-                    Command::Take(tokens[2].to_string())
-                } else {
-                    Command::Take(tokens[1].to_string())
-                }
-            } else {
-                Command::Unknown
+            let start = if cmd == "pick" && tokens.get(1) == Some(&"up") { 2 } else { 1 };
+            let rest = &tokens[start.min(tokens.len())..];
+            
+            // "take coin from chest"
+            if let Some(from) = rest.iter().position(|t| *t == "from") {
+                return match (object(&rest[..from]), object(&rest[from + 1..])) {
+                    (Some(item), Some(container)) => Command::TakeFrom(item, container),
+                    _ => Command::Unknown,
+                };
             }
+            
+            if let Some(except) = all_except(rest) {
+                return Command::TakeAll(except);
+            }
+            object(rest).map_or(Command::Unknown, Command::Take)
         },
-        "drop" => {
-            if tokens.len() > 1 {
-                Command::Drop(tokens[1].to_string())
-            } else {
-                Command::Unknown
+        "drop" => match all_except(&tokens[1..]) {
+            Some(except) => Command::DropAll(except),
+            None => object(&tokens[1..]).map_or(Command::Unknown, Command::Drop),
+        },
+        "put" | "place" | "insert" => {
+            // "put coin in bowl"
+            let Some(i) = tokens.iter().position(|t| matches!(*t, "in" | "into" | "inside")) else {
+                return Command::Unknown;
+            };
+            match (object(&tokens[1..i]), object(&tokens[i + 1..])) {
+                (Some(item), Some(container)) => Command::PutIn(item, container),
+                _ => Command::Unknown,
+            }
+        },
+        "unlock" => {
+            // "unlock north with brass key" or "unlock the old chest with the brass key"
+            let with = tokens.iter().position(|t| *t == "with").unwrap_or(tokens.len());
+            let key = tokens.get(with + 1..).and_then(object);
+            object(&tokens[1..with]).map_or(Command::Unknown, |target| Command::Unlock(target, key))
+        },
+        "open" => object(&tokens[1..]).map_or(Command::Unknown, Command::Open),
+        "close" | "shut" => object(&tokens[1..]).map_or(Command::Unknown, Command::Close),
+        "talk" | "speak" => {
+            // "talk to the old man", "speak with mira"
+            let start = if tokens.get(1).is_some_and(|t| matches!(*t, "to" | "with")) { 2 } else { 1 };
+            object(&tokens[start.min(tokens.len())..]).map_or(Command::Unknown, Command::Talk)
+        },
+        "give" | "offer" | "hand" => {
+            // "give bread to old man", or just "give bread" when there's only one person to give it to
+            let to = tokens.iter().position(|t| *t == "to").unwrap_or(tokens.len());
+            let npc = tokens.get(to + 1..).and_then(object);
+            object(&tokens[1..to]).map_or(Command::Unknown, |item| Command::Give(item, npc))
+        },
+        "ask" => {
+            // "ask old man about the lighthouse", "ask about the lighthouse"; "ask old man" is talking to him
+            let about = tokens.iter().position(|t| *t == "about");
+            match about {
+                Some(i) => match object(&tokens[i + 1..]) {
+                    Some(topic) => Command::Ask(object(&tokens[1..i]), topic),
+                    None => Command::Unknown,
+                },
+                None => object(&tokens[1..]).map_or(Command::Unknown, Command::Talk),
             }
         },
         "inventory" | "i" | "inv" => Command::Inventory,
         "save" => {
             if tokens.len() > 1 {
-                Command::Save(tokens[1].to_string())
+                Command::Save(slot_name(tokens[1]))
             } else {
-                Command::Save("savegame.save".to_string()) // Default save name
+                Command::Save(DEFAULT_SLOT.to_string())
             }
         },
         "load" => {
             if tokens.len() > 1 {
-                Command::Load(tokens[1].to_string())
+                Command::Load(slot_name(tokens[1]))
+            } else {
+                Command::Load(DEFAULT_SLOT.to_string())
+            }
+        },
+        "delete" => {
+            if tokens.len() > 1 {
+                Command::Delete(slot_name(tokens[1]))
             } else {
-                Command::Load("savegame.save".to_string()) // Default load name
+                Command::Unknown
             }
         },
         "saves" | "list" => Command::ListSaves,
+        "again" | "g" => Command::Again,
+        "undo" => Command::Undo,
+        "redo" => Command::Redo,
         "help" | "h" | "?" => Command::Help,
-        _ => Command::Unknown,
+        _ => match parse_phrase(input) {
+            Some(phrase) if phrase.object.is_some() => Command::Do(phrase),
+            _ => Command::Unknown,
+        },
     }
 }
 
@@ -88,18 +314,69 @@ mod tests {
         assert!(matches!(parse_command("l"), Command::Look));
         assert!(matches!(parse_command("examine"), Command::Look));
         assert!(matches!(parse_command("x"), Command::Look));
+        assert!(matches!(parse_command("look at"), Command::Look));
+    }
+
+    #[test]
+    fn test_parse_examine_commands() {
+        for input in ["examine the brass key", "x brass key", "inspect brass key", "look at the brass key", "l at brass key"] {
+            if let Command::Examine(thing) = parse_command(input) {
+                assert_eq!(thing, "brass key", "{}", input);
+            } else {
+                panic!("Expected Examine command for {:?}", input);
+            }
+        }
     }
 
     #[test]
     fn test_parse_direction_commands() {
-        assert!(matches!(parse_command("n"), Command::North));
-        assert!(matches!(parse_command("north"), Command::North));
-        assert!(matches!(parse_command("s"), Command::South));
-        assert!(matches!(parse_command("south"), Command::South));
-        assert!(matches!(parse_command("e"), Command::East));
-        assert!(matches!(parse_command("east"), Command::East));
-        assert!(matches!(parse_command("w"), Command::West));
-        assert!(matches!(parse_command("west"), Command::West));
+        for (input, expected) in [
+            ("n", "north"), ("north", "north"), ("s", "south"), ("e", "east"), ("w", "west"),
+            ("ne", "northeast"), ("nw", "northwest"), ("se", "southeast"), ("southwest", "southwest"),
+            ("u", "up"), ("down", "down"), ("in", "in"), ("out", "out"),
+        ] {
+            if let Command::Go(direction) = parse_command(input) {
+                assert_eq!(direction, expected, "{}", input);
+            } else {
+                panic!("Expected Go command for {:?}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_talk_commands() {
+        for input in ["talk to the old man", "speak with old man", "talk old man"] {
+            if let Command::Talk(npc) = parse_command(input) {
+                assert_eq!(npc, "old man", "{}", input);
+            } else {
+                panic!("Expected Talk command for {:?}", input);
+            }
+        }
+        assert!(matches!(parse_command("talk to"), Command::Unknown));
+        assert!(matches!(parse_command("2"), Command::Choose(2)));
+        assert!(matches!(parse_command("-1"), Command::Unknown));
+    }
+
+    #[test]
+    fn test_parse_give_and_ask_commands() {
+        if let Command::Give(item, Some(npc)) = parse_command("give the stale bread to the old man") {
+            assert_eq!(item, "stale bread");
+            assert_eq!(npc, "old man");
+        } else {
+            panic!("Expected Give command");
+        }
+        assert!(matches!(parse_command("offer bread"), Command::Give(item, None) if item == "bread"));
+        assert!(matches!(parse_command("give to old man"), Command::Unknown));
+
+        if let Command::Ask(Some(npc), topic) = parse_command("ask the old man about the lighthouse") {
+            assert_eq!(npc, "old man");
+            assert_eq!(topic, "lighthouse");
+        } else {
+            panic!("Expected Ask command");
+        }
+        assert!(matches!(parse_command("ask about lighthouse"), Command::Ask(None, topic) if topic == "lighthouse"));
+        assert!(matches!(parse_command("ask old man"), Command::Talk(npc) if npc == "old man"));
+        assert!(matches!(parse_command("ask old man about"), Command::Unknown));
     }
 
     #[test]
@@ -147,6 +424,207 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_unlock_commands() {
+        if let Command::Unlock(direction, key) = parse_command("unlock east with ornate coin") {
+            assert_eq!(direction, "east");
+            assert_eq!(key.as_deref(), Some("ornate coin"));
+        } else {
+            panic!("Expected Unlock command");
+        }
+
+        if let Command::Unlock(direction, key) = parse_command("unlock north") {
+            assert_eq!(direction, "north");
+            assert!(key.is_none());
+        } else {
+            panic!("Expected Unlock command without a key");
+        }
+
+        assert!(matches!(parse_command("unlock"), Command::Unknown));
+    }
+
+    #[test]
+    fn test_parse_open_command() {
+        if let Command::Open(direction) = parse_command("open east") {
+            assert_eq!(direction, "east");
+        } else {
+            panic!("Expected Open command");
+        }
+    }
+
+    #[test]
+    fn test_parse_noun_phrases() {
+        if let Command::Take(item) = parse_command("take the brass key") {
+            assert_eq!(item, "brass key");
+        } else {
+            panic!("Expected Take command");
+        }
+
+        if let Command::Take(item) = parse_command("pick up some red berries") {
+            assert_eq!(item, "red berries");
+        } else {
+            panic!("Expected Take command");
+        }
+
+        if let Command::Drop(item) = parse_command("drop an old map") {
+            assert_eq!(item, "old map");
+        } else {
+            panic!("Expected Drop command");
+        }
+
+        if let Command::Unlock(target, key) = parse_command("unlock the chest with the brass key") {
+            assert_eq!(target, "chest");
+            assert_eq!(key.as_deref(), Some("brass key"));
+        } else {
+            panic!("Expected Unlock command");
+        }
+
+        assert!(matches!(parse_command("take the"), Command::Unknown));
+        assert!(matches!(parse_command("drop a"), Command::Unknown));
+    }
+
+    #[test]
+    fn test_parse_container_commands() {
+        if let Command::TakeFrom(item, container) = parse_command("take coin from wooden chest") {
+            assert_eq!(item, "coin");
+            assert_eq!(container, "wooden chest");
+        } else {
+            panic!("Expected TakeFrom command");
+        }
+
+        if let Command::TakeFrom(item, container) = parse_command("pick up coin from chest") {
+            assert_eq!(item, "coin");
+            assert_eq!(container, "chest");
+        } else {
+            panic!("Expected TakeFrom command");
+        }
+
+        if let Command::PutIn(item, container) = parse_command("put gold coin into bowl") {
+            assert_eq!(item, "gold coin");
+            assert_eq!(container, "bowl");
+        } else {
+            panic!("Expected PutIn command");
+        }
+
+        if let Command::Close(container) = parse_command("close wooden chest") {
+            assert_eq!(container, "wooden chest");
+        } else {
+            panic!("Expected Close command");
+        }
+
+        if let Command::Unlock(target, key) = parse_command("unlock wooden chest with rusty key") {
+            assert_eq!(target, "wooden chest");
+            assert_eq!(key.as_deref(), Some("rusty key"));
+        } else {
+            panic!("Expected Unlock command");
+        }
+
+        assert!(matches!(parse_command("put coin"), Command::Unknown));
+        assert!(matches!(parse_command("put coin in"), Command::Unknown));
+        assert!(matches!(parse_command("take coin from"), Command::Unknown));
+    }
+
+    #[test]
+    fn test_parse_phrases() {
+        let phrase = |verb: &str, object: &str, preposition: &str, indirect: &str| Phrase {
+            verb: verb.to_string(),
+            object: Some(object.to_string()),
+            preposition: Some(preposition.to_string()),
+            indirect: Some(indirect.to_string()),
+        };
+        assert_eq!(parse_phrase("use key on door"), Some(phrase("use", "key", "on", "door")));
+        assert_eq!(parse_phrase("put the coin in a bowl"), Some(phrase("put", "coin", "in", "bowl")));
+        assert_eq!(parse_phrase("give bread to old man"), Some(phrase("give", "bread", "to", "old man")));
+        assert_eq!(parse_phrase("Unlock chest WITH key"), Some(phrase("unlock", "chest", "with", "key")));
+        assert_eq!(parse_phrase("cut vines with the machete"), Some(phrase("cut", "vines", "with", "machete")));
+
+        let simple = parse_phrase("read the old book").unwrap();
+        assert_eq!(simple.verb, "read");
+        assert_eq!(simple.object.as_deref(), Some("old book"));
+        assert!(simple.preposition.is_none() && simple.indirect.is_none());
+
+        let no_object = parse_phrase("look under bed").unwrap();
+        assert!(no_object.object.is_none());
+        assert_eq!(no_object.indirect.as_deref(), Some("bed"));
+
+        assert!(parse_phrase("   ").is_none());
+    }
+
+    #[test]
+    fn test_parse_story_verbs() {
+        if let Command::Do(phrase) = parse_command("cut vines with machete") {
+            assert_eq!(phrase.verb, "cut");
+            assert_eq!(phrase.object.as_deref(), Some("vines"));
+            assert_eq!(phrase.indirect.as_deref(), Some("machete"));
+        } else {
+            panic!("Expected Do command");
+        }
+
+        if let Command::Do(phrase) = parse_command("throw bread to old man") {
+            assert_eq!(phrase.preposition.as_deref(), Some("to"));
+            assert_eq!(phrase.indirect.as_deref(), Some("old man"));
+        } else {
+            panic!("Expected Do command");
+        }
+
+        // Built-in verbs keep their own commands
+        assert!(matches!(parse_command("put coin in bowl"), Command::PutIn(..)));
+        assert!(matches!(parse_command("unlock chest with key"), Command::Unlock(..)));
+        assert!(matches!(parse_command("give bread to old man"), Command::Give(..)));
+    }
+
+    #[test]
+    fn test_parse_all_commands() {
+        assert!(matches!(parse_command("take all"), Command::TakeAll(except) if except.is_empty()));
+        assert!(matches!(parse_command("pick up everything"), Command::TakeAll(except) if except.is_empty()));
+        if let Command::DropAll(except) = parse_command("drop all but the lamp and brass key") {
+            assert_eq!(except, vec!["lamp".to_string(), "brass key".to_string()]);
+        } else {
+            panic!("Expected DropAll command");
+        }
+        if let Command::TakeAll(except) = parse_command("take everything except rope") {
+            assert_eq!(except, vec!["rope".to_string()]);
+        } else {
+            panic!("Expected TakeAll command");
+        }
+
+        // Anything else after "all" is an ordinary noun
+        assert!(matches!(parse_command("take all purpose flour"), Command::Take(_)));
+    }
+
+    #[test]
+    fn test_split_commands() {
+        assert_eq!(split_commands("take key. go north. drop key"), vec!["take key", "go north", "drop key"]);
+        assert_eq!(split_commands("take key then go north"), vec!["take key", "go north"]);
+        assert_eq!(split_commands("take key. then go north.  "), vec!["take key", "go north"]);
+        assert_eq!(split_commands("save game.save"), vec!["save game.save"]);
+        assert!(split_commands("  ").is_empty());
+    }
+
+    #[test]
+    fn test_replace_pronouns() {
+        let nouns = vec!["brass key".to_string(), "lamp".to_string()];
+        assert_eq!(replace_pronouns("drop them", &nouns), Some(vec!["drop brass key".to_string(), "drop lamp".to_string()]));
+        assert_eq!(replace_pronouns("put It in bag", &nouns[..1]), Some(vec!["put brass key in bag".to_string()]));
+        assert_eq!(replace_pronouns("examine it", &[]), Some(Vec::new()));
+        assert_eq!(replace_pronouns("examine it", &nouns), Some(Vec::new()));
+        assert_eq!(replace_pronouns("take item", &nouns), None);
+    }
+
+    #[test]
+    fn test_verb_list_matches_parser() {
+        for verb in VERBS {
+            let command = parse_command(&format!("{} key in box", verb));
+            assert!(!matches!(command, Command::Unknown | Command::Do(_)), "{} isn't a built-in verb", verb);
+        }
+    }
+
+    #[test]
+    fn test_parse_again_command() {
+        assert!(matches!(parse_command("again"), Command::Again));
+        assert!(matches!(parse_command("g"), Command::Again));
+    }
+
     #[test]
     fn test_parse_inventory_commands() {
         assert!(matches!(parse_command("inventory"), Command::Inventory));
@@ -156,15 +634,22 @@ mod tests {
 
     #[test]
     fn test_parse_save_commands() {
-        if let Command::Save(filename) = parse_command("save mygame.save") {
-            assert_eq!(filename, "mygame.save");
+        if let Command::Save(slot) = parse_command("save mygame") {
+            assert_eq!(slot, "mygame");
+        } else {
+            panic!("Expected Save command");
+        }
+
+        // The old file extension is dropped
+        if let Command::Save(slot) = parse_command("save mygame.save") {
+            assert_eq!(slot, "mygame");
         } else {
             panic!("Expected Save command");
         }
 
         // Test default save name
         if let Command::Save(filename) = parse_command("save") {
-            assert_eq!(filename, "savegame.save");
+            assert_eq!(filename, "quicksave");
         } else {
             panic!("Expected Save command with default name");
         }
@@ -172,26 +657,42 @@ mod tests {
 
     #[test]
     fn test_parse_load_commands() {
-        if let Command::Load(filename) = parse_command("load mygame.save") {
-            assert_eq!(filename, "mygame.save");
+        if let Command::Load(filename) = parse_command("load mygame") {
+            assert_eq!(filename, "mygame");
         } else {
             panic!("Expected Load command");
         }
 
         // Test default load name
         if let Command::Load(filename) = parse_command("load") {
-            assert_eq!(filename, "savegame.save");
+            assert_eq!(filename, "quicksave");
         } else {
             panic!("Expected Load command with default name");
         }
     }
 
+    #[test]
+    fn test_parse_delete_command() {
+        if let Command::Delete(slot) = parse_command("delete mygame") {
+            assert_eq!(slot, "mygame");
+        } else {
+            panic!("Expected Delete command");
+        }
+        assert!(matches!(parse_command("delete"), Command::Unknown));
+    }
+
     #[test]
     fn test_parse_list_saves_command() {
         assert!(matches!(parse_command("saves"), Command::ListSaves));
         assert!(matches!(parse_command("list"), Command::ListSaves));
     }
 
+    #[test]
+    fn test_parse_undo_redo_commands() {
+        assert!(matches!(parse_command("undo"), Command::Undo));
+        assert!(matches!(parse_command("redo"), Command::Redo));
+    }
+
     #[test]
     fn test_parse_help_command() {
         assert!(matches!(parse_command("help"), Command::Help));
@@ -1,14 +1,152 @@
+use crate::engine::{action::{Action, Reaction}, condition::Condition, exit::Exit};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(try_from = "RoomDef", into = "RoomDef")]
 pub struct Room {
     pub title: String,
     pub description: String,
-    #[serde(flatten)]
-    pub exits: HashMap<String, String>, // direction -> room_id
+    pub exits: HashMap<String, Exit>, // direction -> exit
     pub items: Vec<String>, // item IDs
-}// Add basic data structures :D
+    pub on_enter: Vec<Action>, // run each time the player walks in
+    pub variants: Vec<DescriptionVariant>, // checked in order before `description`
+    pub short_description: Option<String>, // shown instead of `description` on revisits
+    pub dark: bool, // needs a light source to see in
+    pub reactions: Vec<Reaction>, // responses to story verbs used here, after the object's own
+    pub scenery: HashMap<String, String>, // noun -> description, for things that can be examined but not taken
+}
+
+/// An alternative room description, used while its condition holds:
+///
+/// ```toml
+/// [[rooms.cellar.variants]]
+/// when = "dark"
+/// text = "It is pitch black. You might be eaten by something."
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct DescriptionVariant {
+    pub when: Condition,
+    pub text: String,
+}
+
+// On-disk shape of a room. Exits may live in an `exits = { ... }` table or be
+// written as direction keys (room IDs or exit tables) next to the other
+// fields; both end up in `Room::exits`. Rooms are always written back with an
+// `exits` table.
+#[derive(Serialize, Deserialize)]
+struct RoomDef {
+    title: String,
+    description: String,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    exits: HashMap<String, Exit>,
+    items: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    on_enter: Vec<Action>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    variants: Vec<DescriptionVariant>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    short_description: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    dark: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    reactions: Vec<Reaction>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    scenery: HashMap<String, String>,
+    #[serde(flatten)]
+    directions: HashMap<String, toml::Value>,
+}
+
+impl TryFrom<RoomDef> for Room {
+    type Error = String;
+
+    fn try_from(def: RoomDef) -> Result<Self, Self::Error> {
+        let mut exits = def.exits;
+
+        for (direction, value) in def.directions {
+            let exit = match value {
+                toml::Value::String(to) => Exit::new(&to),
+                toml::Value::Table(table) => toml::Value::Table(table)
+                    .try_into::<Exit>()
+                    .map_err(|e| format!("invalid exit `{}`: {}", direction, e))?,
+                other => {
+                    return Err(format!(
+                        "unknown room field `{}` (a {}); direction exits must be room IDs or exit tables",
+                        direction,
+                        other.type_str()
+                    ))
+                }
+            };
+
+            if exits.contains_key(&direction) {
+                return Err(format!("exit `{}` is declared more than once", direction));
+            }
+            exits.insert(direction, exit);
+        }
+
+        Ok(Self {
+            title: def.title,
+            description: def.description,
+            exits,
+            items: def.items,
+            on_enter: def.on_enter,
+            variants: def.variants,
+            short_description: def.short_description,
+            dark: def.dark,
+            reactions: def.reactions,
+            scenery: def.scenery,
+        })
+    }
+}
+
+impl Room {
+    /// The scenery noun and description the player means by `phrase`, if any.
+    /// Like items, every word must come from the noun, so "blocks" finds
+    /// "stone blocks".
+    pub fn scenery_for(&self, phrase: &str) -> Option<(&str, &str)> {
+        find_noun(&self.scenery, phrase)
+    }
+}
+
+/// The entry in a noun -> text table the player means by `phrase`: every
+/// word must come from the noun, and the shortest noun wins, so "blocks"
+/// prefers "blocks" over "stone blocks". Used for scenery and NPC topics.
+pub fn find_noun<'a>(entries: &'a HashMap<String, String>, phrase: &str) -> Option<(&'a str, &'a str)> {
+    let phrase = phrase.to_lowercase();
+    let words: Vec<&str> = phrase.split_whitespace().collect();
+    if words.is_empty() {
+        return None;
+    }
+
+    let mut matches: Vec<(&str, &str)> = entries.iter()
+        .filter(|(noun, _)| {
+            let noun = noun.to_lowercase();
+            words.iter().all(|word| noun.split_whitespace().any(|known| known == *word))
+        })
+        .map(|(noun, text)| (noun.as_str(), text.as_str()))
+        .collect();
+    matches.sort_by_key(|(noun, _)| (noun.len(), *noun));
+    matches.into_iter().next()
+}
+
+impl From<Room> for RoomDef {
+    fn from(room: Room) -> Self {
+        Self {
+            title: room.title,
+            description: room.description,
+            exits: room.exits,
+            items: room.items,
+            on_enter: room.on_enter,
+            variants: room.variants,
+            short_description: room.short_description,
+            dark: room.dark,
+            reactions: room.reactions,
+            scenery: room.scenery,
+            directions: HashMap::new(),
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -18,19 +156,188 @@ mod tests {
     #[test]
     fn test_room_creation() {
         let mut exits = HashMap::new();
-        exits.insert("north".to_string(), "other_room".to_string());
-        
+        exits.insert("north".to_string(), Exit::new("other_room"));
+
         let room = Room {
             title: "Test Room".to_string(),
             description: "A test room".to_string(),
             exits,
             items: vec!["key".to_string()],
+            ..Default::default()
         };
 
         assert_eq!(room.title, "Test Room");
         assert_eq!(room.description, "A test room");
         assert!(room.exits.contains_key("north"));
-        assert_eq!(room.exits.get("north").unwrap(), "other_room");
+        assert_eq!(room.exits.get("north").unwrap().to, "other_room");
         assert!(room.items.contains(&"key".to_string()));
     }
+
+    #[test]
+    fn test_room_inline_exits_table() {
+        let room: Room = toml::from_str(r#"
+title = "Hallway"
+description = "A long hallway."
+exits = { south = "entrance", east = "parlor" }
+items = []
+"#).unwrap();
+
+        assert_eq!(room.exits.len(), 2);
+        assert_eq!(room.exits["south"].to, "entrance");
+        assert_eq!(room.exits["east"].to, "parlor");
+    }
+
+    #[test]
+    fn test_room_flattened_direction_keys() {
+        let room: Room = toml::from_str(r#"
+title = "Temple Entrance"
+description = "Massive stone blocks."
+south = "jungle_path"
+inside = "temple_hall"
+items = ["torch"]
+"#).unwrap();
+
+        assert_eq!(room.exits.len(), 2);
+        assert_eq!(room.exits["south"].to, "jungle_path");
+        assert_eq!(room.exits["inside"].to, "temple_hall");
+        assert_eq!(room.items, vec!["torch".to_string()]);
+    }
+
+    #[test]
+    fn test_room_mixed_exit_syntax() {
+        let room: Room = toml::from_str(r#"
+title = "Crossroads"
+description = "Paths in every direction."
+west = "forest"
+exits = { north = "village" }
+items = []
+"#).unwrap();
+
+        assert_eq!(room.exits["north"].to, "village");
+        assert_eq!(room.exits["west"].to, "forest");
+    }
+
+    #[test]
+    fn test_room_flattened_exit_table() {
+        let room: Room = toml::from_str(r#"
+title = "Temple Hall"
+description = "Candles flicker."
+east = { to = "treasure_chamber", locked = true, key = "ancient_coin" }
+items = []
+"#).unwrap();
+
+        let exit = &room.exits["east"];
+        assert_eq!(exit.to, "treasure_chamber");
+        assert!(exit.locked);
+        assert_eq!(exit.key.as_deref(), Some("ancient_coin"));
+    }
+
+    #[test]
+    fn test_room_description_variants() {
+        let room: Room = toml::from_str(r#"
+title = "Cellar"
+description = "Shelves of dusty jars line the walls."
+short_description = "The cellar."
+dark = true
+items = []
+
+[[variants]]
+when = "dark"
+text = "It is pitch black."
+
+[[variants]]
+when = "first_visit"
+text = "You creep down the stairs into a cellar full of dusty jars."
+"#).unwrap();
+
+        assert!(room.dark);
+        assert_eq!(room.short_description.as_deref(), Some("The cellar."));
+        assert_eq!(room.variants.len(), 2);
+        assert_eq!(room.variants[0].when.source(), "dark");
+        assert_eq!(room.variants[1].text, "You creep down the stairs into a cellar full of dusty jars.");
+    }
+
+    #[test]
+    fn test_room_reactions() {
+        let room: Room = toml::from_str(r#"
+title = "Temple Hall"
+description = "Candles flicker."
+items = []
+back = "temple_entrance"
+
+[[reactions]]
+verb = "pray"
+message = "A faint warmth spreads through the hall."
+"#).unwrap();
+
+        assert_eq!(room.reactions.len(), 1);
+        assert!(room.reactions[0].fits("pray", None));
+        assert_eq!(room.exits.len(), 1);
+    }
+
+    #[test]
+    fn test_room_scenery() {
+        let room: Room = toml::from_str(r#"
+title = "Dark Hallway"
+description = "Portraits line the walls."
+items = []
+scenery = { portraits = "Stern faces follow you.", "stone blocks" = "Worn smooth." }
+"#).unwrap();
+
+        assert_eq!(room.scenery_for("portraits"), Some(("portraits", "Stern faces follow you.")));
+        assert_eq!(room.scenery_for("Stone Blocks"), Some(("stone blocks", "Worn smooth.")));
+        assert_eq!(room.scenery_for("blocks"), Some(("stone blocks", "Worn smooth.")));
+        assert_eq!(room.scenery_for("walls"), None);
+        assert_eq!(room.scenery_for(""), None);
+    }
+
+    #[test]
+    fn test_room_rejects_non_string_unknown_key() {
+        let result: Result<Room, _> = toml::from_str(r#"
+title = "Shore"
+description = "A desolate shore."
+npcs = ["old_man"]
+items = []
+"#);
+
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("npcs"));
+    }
+
+    #[test]
+    fn test_room_rejects_duplicate_exit() {
+        let result: Result<Room, _> = toml::from_str(r#"
+title = "Hallway"
+description = "A long hallway."
+north = "attic"
+exits = { north = "parlor" }
+items = []
+"#);
+
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("north"));
+    }
+
+    #[test]
+    fn test_room_round_trip() {
+        let mut exits = HashMap::new();
+        exits.insert("north".to_string(), Exit::new("hallway"));
+        exits.insert("inside".to_string(), Exit::new("temple_hall"));
+
+        let room = Room {
+            title: "Entrance".to_string(),
+            description: "A creaky door.".to_string(),
+            exits,
+            items: vec!["key".to_string()],
+            ..Default::default()
+        };
+
+        let serialized = toml::to_string(&room).unwrap();
+        let restored: Room = toml::from_str(&serialized).unwrap();
+        assert_eq!(restored, room);
+
+        let json = serde_json::to_string(&room).unwrap();
+        let restored: Room = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, room);
+    }
 }
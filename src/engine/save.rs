@@ -0,0 +1,219 @@
+use crate::engine::{condition::FlagValue, game::GameError};
+use crate::story::loader::Story;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Bump this whenever `SavedGame` changes shape. Saves from before versioning
+/// have no `version` field and read as version 0.
+pub const SAVE_VERSION: u32 = 5;
+
+/// Everything about a game in progress that can differ from the story file.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct SavedGame {
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub story: Option<String>, // fingerprint of the story the save was made against
+    pub current_room: String,
+    pub inventory: HashSet<String>,
+    #[serde(default)]
+    pub flags: HashMap<String, FlagValue>,
+    #[serde(default)]
+    pub visits: HashMap<String, u32>,
+    #[serde(default)]
+    pub turns: u32,
+    #[serde(default)]
+    pub rooms: HashMap<String, RoomState>, // room_id -> state
+    #[serde(default)]
+    pub containers: HashMap<String, ContainerState>, // item_id -> state
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub npcs: HashMap<String, NpcState>, // npc_id -> state
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub talking_to: Option<String>, // npc_id, while the player is in a conversation
+    #[serde(default)]
+    pub saved_at: u64, // seconds since the Unix epoch
+    #[serde(default)]
+    pub playtime: u64, // seconds
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub undo: Vec<SavedGame>, // oldest first, only kept when the story asks for it
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct RoomState {
+    pub items: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub locked_exits: Vec<String>, // directions
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct ContainerState {
+    pub open: bool,
+    pub locked: bool,
+    pub contents: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct NpcState {
+    pub location: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patrol_step: Option<usize>, // index into the NPC's patrol route
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dialogue_node: Option<String>, // where the next or current conversation is
+}
+
+impl SavedGame {
+    /// Whether both describe the same world, however many turns apart.
+    pub fn same_world(&self, other: &SavedGame) -> bool {
+        let when = |saved: &SavedGame| SavedGame { turns: 0, saved_at: 0, playtime: 0, ..saved.clone() };
+        when(self) == when(other)
+    }
+
+    pub fn to_json(&self) -> Result<String, GameError> {
+        serde_json::to_string(self).map_err(|e| GameError::Save(e.to_string()))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, GameError> {
+        let saved: SavedGame = serde_json::from_str(json)
+            .map_err(|e| GameError::Save(format!("not a valid save file ({})", e)))?;
+
+        if saved.version > SAVE_VERSION {
+            return Err(GameError::Save(format!(
+                "the save is version {} but this version of nemu only reads up to version {}",
+                saved.version, SAVE_VERSION
+            )));
+        }
+        Ok(saved)
+    }
+
+    /// Makes sure the save belongs to `story` and only refers to rooms and
+    /// items that exist in it.
+    pub fn check_story(&self, story: &Story) -> Result<(), GameError> {
+        if let Some(saved_fingerprint) = &self.story {
+            if *saved_fingerprint != fingerprint(story) {
+                return Err(GameError::Save(format!(
+                    "this save was made with a different story than \"{}\"",
+                    story.story.title
+                )));
+            }
+        }
+
+        let unknown_room = std::iter::once(&self.current_room)
+            .chain(self.rooms.keys())
+            .chain(self.npcs.values().map(|npc| &npc.location))
+            .find(|room_id| !story.rooms.contains_key(*room_id));
+        if let Some(room_id) = unknown_room {
+            return Err(GameError::Save(format!("the save refers to an unknown room `{}`", room_id)));
+        }
+
+        let unknown_item = self.inventory.iter()
+            .chain(self.rooms.values().flat_map(|room| &room.items))
+            .chain(self.containers.keys())
+            .chain(self.containers.values().flat_map(|container| &container.contents))
+            .find(|item_id| !story.items.contains_key(*item_id));
+        if let Some(item_id) = unknown_item {
+            return Err(GameError::Save(format!("the save refers to an unknown item `{}`", item_id)));
+        }
+
+        if let Some(npc_id) = self.npcs.keys().find(|npc_id| !story.npcs.contains_key(*npc_id)) {
+            return Err(GameError::Save(format!("the save refers to an unknown NPC `{}`", npc_id)));
+        }
+
+        Ok(())
+    }
+}
+
+/// Identifies a story by its title and the IDs of its rooms, items and NPCs, so
+/// fixing a typo in a description doesn't orphan existing saves but loading
+/// a save into a different story is caught.
+pub fn fingerprint(story: &Story) -> String {
+    let mut rooms: Vec<&String> = story.rooms.keys().collect();
+    let mut items: Vec<&String> = story.items.keys().collect();
+    let mut npcs: Vec<&String> = story.npcs.keys().collect();
+    rooms.sort();
+    items.sort();
+    npcs.sort();
+
+    // FNV-1a, which unlike std's hasher is guaranteed stable between builds
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let parts = std::iter::once(&story.story.title).chain(rooms).chain(items).chain(npcs);
+    for part in parts {
+        for byte in part.bytes().chain(std::iter::once(0)) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{item::Item, room::Room};
+    use crate::story::loader::StoryInfo;
+
+    fn story(title: &str) -> Story {
+        let mut story = Story {
+            story: StoryInfo {
+                title: title.to_string(),
+                start_room: "start".to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        story.rooms.insert("start".to_string(), Room::default());
+        story.items.insert("key".to_string(), Item::default());
+        story
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let original = story("Test Story");
+        let mut reworded = story("Test Story");
+        reworded.rooms.get_mut("start").unwrap().description = "Freshly painted.".to_string();
+        assert_eq!(fingerprint(&original), fingerprint(&reworded));
+
+        let mut extra_room = story("Test Story");
+        extra_room.rooms.insert("attic".to_string(), Room::default());
+        assert_ne!(fingerprint(&original), fingerprint(&extra_room));
+        assert_ne!(fingerprint(&original), fingerprint(&story("Other Story")));
+    }
+
+    #[test]
+    fn test_check_story() {
+        let story = story("Test Story");
+        let saved = SavedGame {
+            version: SAVE_VERSION,
+            story: Some(fingerprint(&story)),
+            current_room: "start".to_string(),
+            ..Default::default()
+        };
+        assert!(saved.check_story(&story).is_ok());
+
+        let other = SavedGame { story: Some("0000000000000000".to_string()), ..saved.clone() };
+        assert!(matches!(other.check_story(&story), Err(GameError::Save(message)) if message.contains("different story")));
+
+        let mut stray_item = saved.clone();
+        stray_item.inventory.insert("lamp".to_string());
+        assert!(matches!(stray_item.check_story(&story), Err(GameError::Save(message)) if message.contains("lamp")));
+
+        let mut stray_npc = saved.clone();
+        stray_npc.npcs.insert("ghost".to_string(), NpcState { location: "start".to_string(), ..Default::default() });
+        assert!(matches!(stray_npc.check_story(&story), Err(GameError::Save(message)) if message.contains("ghost")));
+
+        let lost = SavedGame { current_room: "attic".to_string(), ..saved };
+        assert!(lost.check_story(&story).is_err());
+    }
+
+    #[test]
+    fn test_save_versions() {
+        // Saves from before versioning still load
+        let legacy = SavedGame::from_json(r#"{"current_room":"start","inventory":["key"]}"#).unwrap();
+        assert_eq!(legacy.version, 0);
+        assert!(legacy.story.is_none());
+        assert!(legacy.inventory.contains("key"));
+
+        let future = format!(r#"{{"version":{},"current_room":"start","inventory":[]}}"#, SAVE_VERSION + 1);
+        assert!(SavedGame::from_json(&future).is_err());
+        assert!(SavedGame::from_json("not json").is_err());
+    }
+}
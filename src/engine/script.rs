@@ -0,0 +1,463 @@
+use crate::engine::{condition::FlagValue, game::GameError};
+use mlua::{ChunkMode, Function, HookTriggers, IntoLuaMulti, Lua, LuaOptions, StdLib, Value, Variadic};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// The part of the game state a script can see and change while a hook runs.
+#[derive(Debug, Default, Clone)]
+pub struct ScriptWorld {
+    pub current_room: String,
+    pub inventory: HashSet<String>,
+    pub flags: HashMap<String, FlagValue>,
+    pub visits: HashMap<String, u32>,
+    pub output: Vec<String>,
+}
+
+/// Resource limits for a story script. Stories come from strangers, so a
+/// script that runs away is stopped rather than trusted.
+///
+/// Counting instructions isn't enough on its own: one call like
+/// `string.rep("x", 1e7)` is a single instruction but takes real time, so
+/// each call also gets a wall-clock deadline. Like the instruction count it's
+/// only checked every few instructions, so a call can overrun it by the cost
+/// of one library function, which the memory limit keeps small.
+#[derive(Debug, Clone, Copy)]
+pub struct ScriptLimits {
+    pub memory: usize, // bytes for the whole Lua state
+    pub instructions: u32, // per hook call (and for loading the script)
+    pub time: Duration, // per hook call (and for loading the script)
+}
+
+impl Default for ScriptLimits {
+    fn default() -> Self {
+        Self {
+            memory: 16 * 1024 * 1024,
+            instructions: 1_000_000,
+            time: Duration::from_secs(1),
+        }
+    }
+}
+
+// How often the instruction counter is checked
+const HOOK_INTERVAL: u32 = 1000;
+
+// Base library functions that could load code or catch the errors used to
+// stop a runaway script
+const BLOCKED_GLOBALS: &[&str] = &[
+    "load", "loadstring", "loadfile", "dofile", "require",
+    "pcall", "xpcall", "collectgarbage",
+];
+
+/// A story's Lua script. Hooks are plain global functions the script may
+/// define: `on_enter(room)`, `on_take(item)`, `on_turn(turn)` and
+/// `on_command(verb, object, preposition, indirect)`. They talk to the game
+/// through the `game` table:
+///
+/// ```lua
+/// function on_command(verb, object, preposition, indirect)
+///     if verb == "pray" and game.room() == "temple_hall" then
+///         game.set_flag("blessed", true)
+///         print("A warm light surrounds you.")
+///         return true -- handled, skip the built-in command
+///     end
+/// end
+/// ```
+///
+/// Scripts run in a sandbox: only the `string`, `table`, `math` and `utf8`
+/// libraries are available (no `io`, `os`, `require`, `load` or coroutines),
+/// and each call is held to the [`ScriptLimits`].
+pub struct ScriptEngine {
+    lua: Lua,
+    world: Rc<RefCell<ScriptWorld>>,
+    instructions: Rc<Cell<u32>>, // run so far in the current call
+    started: Rc<Cell<Instant>>, // when the current call began
+    limits: ScriptLimits,
+}
+
+impl ScriptEngine {
+    pub fn new(
+        source: &str,
+        name: &str,
+        rooms: HashSet<String>,
+        items: HashSet<String>,
+        limits: ScriptLimits,
+    ) -> Result<Self, GameError> {
+        let lua = Lua::new_with(
+            StdLib::STRING | StdLib::TABLE | StdLib::MATH | StdLib::UTF8,
+            LuaOptions::default(),
+        ).map_err(script_error)?;
+        for name in BLOCKED_GLOBALS {
+            lua.globals().raw_remove(*name).map_err(script_error)?;
+        }
+
+        let world = Rc::new(RefCell::new(ScriptWorld::default()));
+        register_api(&lua, &world, Rc::new(rooms), Rc::new(items)).map_err(script_error)?;
+
+        let instructions = Rc::new(Cell::new(0u32));
+        let started = Rc::new(Cell::new(Instant::now()));
+        let (counter, clock) = (Rc::clone(&instructions), Rc::clone(&started));
+        lua.set_hook(HookTriggers::new().every_nth_instruction(HOOK_INTERVAL), move |_, _| {
+            counter.set(counter.get().saturating_add(HOOK_INTERVAL));
+            if counter.get() > limits.instructions {
+                return Err(mlua::Error::RuntimeError("instruction limit exceeded".to_string()));
+            }
+            if clock.get().elapsed() > limits.time {
+                return Err(mlua::Error::RuntimeError("time limit exceeded".to_string()));
+            }
+            Ok(())
+        });
+        lua.set_memory_limit(limits.memory).map_err(script_error)?;
+
+        let engine = Self { lua, world, instructions, started, limits };
+        let result = engine.lua.load(source).set_name(name).set_mode(ChunkMode::Text).exec();
+        engine.check(result)?;
+        Ok(engine)
+    }
+
+    /// Calls a hook if the script defines it. `world` is handed to the script
+    /// for the duration of the call and holds its changes afterwards. Returns
+    /// whether the hook reported that it handled the event.
+    pub fn call_hook<'lua, A>(
+        &'lua self,
+        hook: &str,
+        args: A,
+        world: &mut ScriptWorld,
+    ) -> Result<bool, GameError>
+    where
+        A: IntoLuaMulti<'lua>,
+    {
+        let function = match self.lua.globals().get::<_, Value>(hook).map_err(script_error)? {
+            Value::Function(function) => function,
+            Value::Nil => return Ok(false),
+            _ => return Err(GameError::Script(format!("`{}` is not a function", hook))),
+        };
+
+        self.instructions.set(0);
+        self.started.set(Instant::now());
+        std::mem::swap(&mut *self.world.borrow_mut(), world);
+        let result = function.call::<_, Value>(args);
+        std::mem::swap(&mut *self.world.borrow_mut(), world);
+
+        match self.check(result)? {
+            Value::Nil | Value::Boolean(false) => Ok(false),
+            _ => Ok(true),
+        }
+    }
+
+    // Turns a Lua error into a GameError, telling limit kills apart from
+    // ordinary script errors
+    fn check<T>(&self, result: mlua::Result<T>) -> Result<T, GameError> {
+        result.map_err(|error| {
+            if self.instructions.get() > self.limits.instructions {
+                GameError::ScriptKilled(format!(
+                    "ran for more than {} instructions",
+                    self.limits.instructions
+                ))
+            } else if self.started.get().elapsed() > self.limits.time {
+                GameError::ScriptKilled(format!("ran for more than {:?}", self.limits.time))
+            } else if is_memory_error(&error) {
+                GameError::ScriptKilled(format!("used more than {} bytes of memory", self.limits.memory))
+            } else {
+                script_error(error)
+            }
+        })
+    }
+}
+
+fn is_memory_error(error: &mlua::Error) -> bool {
+    match error {
+        mlua::Error::MemoryError(_) => true,
+        mlua::Error::CallbackError { cause, .. } => is_memory_error(cause),
+        _ => false,
+    }
+}
+
+fn script_error(error: mlua::Error) -> GameError {
+    GameError::Script(error.to_string())
+}
+
+fn register_api(
+    lua: &Lua,
+    world: &Rc<RefCell<ScriptWorld>>,
+    rooms: Rc<HashSet<String>>,
+    items: Rc<HashSet<String>>,
+) -> mlua::Result<()> {
+    let api = lua.create_table()?;
+
+    let w = Rc::clone(world);
+    api.set("room", lua.create_function(move |_, ()| Ok(w.borrow().current_room.clone()))?)?;
+
+    let w = Rc::clone(world);
+    api.set("move_to", lua.create_function(move |_, room: String| {
+        if !rooms.contains(&room) {
+            return Err(mlua::Error::RuntimeError(format!("no room with ID `{}`", room)));
+        }
+        w.borrow_mut().current_room = room;
+        Ok(())
+    })?)?;
+
+    let w = Rc::clone(world);
+    api.set("has", lua.create_function(move |_, item: String| Ok(w.borrow().inventory.contains(&item)))?)?;
+
+    let w = Rc::clone(world);
+    api.set("give", lua.create_function(move |_, item: String| {
+        if !items.contains(&item) {
+            return Err(mlua::Error::RuntimeError(format!("no item with ID `{}`", item)));
+        }
+        w.borrow_mut().inventory.insert(item);
+        Ok(())
+    })?)?;
+
+    let w = Rc::clone(world);
+    api.set("remove", lua.create_function(move |_, item: String| Ok(w.borrow_mut().inventory.remove(&item)))?)?;
+
+    let w = Rc::clone(world);
+    api.set("get_flag", lua.create_function(move |lua, name: String| {
+        match w.borrow().flags.get(&name) {
+            Some(value) => flag_to_lua(lua, value),
+            None => Ok(Value::Nil),
+        }
+    })?)?;
+
+    let w = Rc::clone(world);
+    api.set("set_flag", lua.create_function(move |_, (name, value): (String, Value)| {
+        match flag_from_lua(value)? {
+            Some(value) => w.borrow_mut().flags.insert(name, value),
+            None => w.borrow_mut().flags.remove(&name),
+        };
+        Ok(())
+    })?)?;
+
+    let w = Rc::clone(world);
+    api.set("visits", lua.create_function(move |_, room: String| {
+        Ok(w.borrow().visits.get(&room).copied().unwrap_or(0))
+    })?)?;
+
+    let w = Rc::clone(world);
+    let print = lua.create_function(move |_, values: Variadic<Value>| {
+        let line: Vec<String> = values.iter().map(display_value).collect();
+        w.borrow_mut().output.push(line.join("\t"));
+        Ok(())
+    })?;
+    api.set("print", print.clone())?;
+
+    lua.globals().set("game", api)?;
+    lua.globals().set::<_, Function>("print", print)?;
+    Ok(())
+}
+
+fn flag_to_lua<'lua>(lua: &'lua Lua, value: &FlagValue) -> mlua::Result<Value<'lua>> {
+    Ok(match value {
+        FlagValue::Bool(b) => Value::Boolean(*b),
+        FlagValue::Int(n) => Value::Integer(*n),
+        FlagValue::Str(s) => Value::String(lua.create_string(s)?),
+    })
+}
+
+// `nil` clears the flag
+fn flag_from_lua(value: Value) -> mlua::Result<Option<FlagValue>> {
+    match value {
+        Value::Nil => Ok(None),
+        Value::Boolean(b) => Ok(Some(FlagValue::Bool(b))),
+        Value::Integer(n) => Ok(Some(FlagValue::Int(n))),
+        Value::Number(n) if n.fract() == 0.0 => Ok(Some(FlagValue::Int(n as i64))),
+        Value::String(s) => Ok(Some(FlagValue::Str(s.to_str()?.to_string()))),
+        other => Err(mlua::Error::RuntimeError(format!(
+            "flags can only hold booleans, integers or strings, not {}",
+            other.type_name()
+        ))),
+    }
+}
+
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::Nil => "nil".to_string(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Integer(n) => n.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.to_string_lossy().to_string(),
+        other => other.type_name().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn engine(source: &str) -> ScriptEngine {
+        let rooms = HashSet::from(["hall".to_string(), "vault".to_string()]);
+        let items = HashSet::from(["key".to_string()]);
+        ScriptEngine::new(source, "test.lua", rooms, items, ScriptLimits::default()).unwrap()
+    }
+
+    fn limited_engine(source: &str) -> ScriptEngine {
+        let limits = ScriptLimits {
+            memory: 1024 * 1024,
+            instructions: 100_000,
+            time: Duration::from_secs(1),
+        };
+        ScriptEngine::new(source, "hostile.lua", HashSet::new(), HashSet::new(), limits).unwrap()
+    }
+
+    fn world() -> ScriptWorld {
+        ScriptWorld {
+            current_room: "hall".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_missing_hook_is_ignored() {
+        let engine = engine("");
+        let mut world = world();
+        assert!(!engine.call_hook("on_turn", (1,), &mut world).unwrap());
+    }
+
+    #[test]
+    fn test_hook_changes_world() {
+        let engine = engine(r#"
+function on_command(verb, noun)
+    if verb == "pray" and noun == nil then
+        game.set_flag("blessed", true)
+        game.set_flag("prayers", (game.get_flag("prayers") or 0) + 1)
+        game.give("key")
+        game.move_to("vault")
+        print("A warm light surrounds you.", game.room())
+        return true
+    end
+end
+"#);
+        let mut world = world();
+
+        assert!(!engine.call_hook("on_command", ("look", None::<String>), &mut world).unwrap());
+        assert!(engine.call_hook("on_command", ("pray", None::<String>), &mut world).unwrap());
+        assert_eq!(world.flags["blessed"], FlagValue::Bool(true));
+        assert_eq!(world.flags["prayers"], FlagValue::Int(1));
+        assert!(world.inventory.contains("key"));
+        assert_eq!(world.current_room, "vault");
+        assert_eq!(world.output, vec!["A warm light surrounds you.\tvault".to_string()]);
+    }
+
+    #[test]
+    fn test_hook_errors() {
+        let engine = engine(r#"
+function on_enter(room) game.move_to("nowhere") end
+function on_take(item) game.set_flag("bad", {}) end
+on_turn = 5
+"#);
+        let mut world = world();
+
+        assert!(matches!(engine.call_hook("on_enter", ("hall",), &mut world), Err(GameError::Script(_))));
+        assert!(matches!(engine.call_hook("on_take", ("key",), &mut world), Err(GameError::Script(_))));
+        assert!(matches!(engine.call_hook("on_turn", (1,), &mut world), Err(GameError::Script(_))));
+    }
+
+    #[test]
+    fn test_syntax_error_fails_to_load() {
+        let result = ScriptEngine::new("function oops(", "bad.lua", HashSet::new(), HashSet::new(), ScriptLimits::default());
+        assert!(matches!(result, Err(GameError::Script(_))));
+    }
+
+    #[test]
+    fn test_bytecode_is_refused() {
+        // Precompiled chunks could get round the sandbox, so only source loads
+        let source = "\x1bLua\x54\x00\x19\r\n\x1a\n";
+        let result = ScriptEngine::new(source, "hostile.lua", HashSet::new(), HashSet::new(), ScriptLimits::default());
+        assert!(matches!(&result, Err(GameError::Script(message)) if message.contains("binary chunk")), "{:?}", result.err());
+    }
+
+    #[test]
+    fn test_sandbox_hides_dangerous_globals() {
+        let engine = limited_engine(r#"
+function on_command(verb)
+    if verb == "check" then
+        for _, name in ipairs({"io", "os", "require", "load", "loadfile", "dofile", "pcall", "xpcall", "debug", "package", "coroutine", "collectgarbage"}) do
+            if _G[name] ~= nil then print(name) end
+        end
+        return true
+    end
+    if verb == "read" then
+        return io.open("/etc/passwd"):read("a")
+    end
+end
+"#);
+        let mut world = world();
+
+        assert!(engine.call_hook("on_command", ("check",), &mut world).unwrap());
+        assert!(world.output.is_empty(), "exposed globals: {:?}", world.output);
+
+        let result = engine.call_hook("on_command", ("read",), &mut world);
+        assert!(matches!(result, Err(GameError::Script(_))));
+    }
+
+    #[test]
+    fn test_infinite_loop_is_killed() {
+        let engine = limited_engine(r#"
+function on_turn(turn)
+    while true do end
+end
+function on_enter(room) print("still alive") end
+"#);
+        let mut world = world();
+
+        let result = engine.call_hook("on_turn", (1,), &mut world);
+        assert!(matches!(result, Err(GameError::ScriptKilled(_))));
+
+        // The budget is per call, so the script keeps working afterwards
+        engine.call_hook("on_enter", ("hall",), &mut world).unwrap();
+        assert_eq!(world.output, vec!["still alive".to_string()]);
+    }
+
+    #[test]
+    fn test_infinite_loop_while_loading_is_killed() {
+        let limits = ScriptLimits {
+            memory: 1024 * 1024,
+            instructions: 100_000,
+            time: Duration::from_secs(1),
+        };
+        let result = ScriptEngine::new("repeat until false", "hostile.lua", HashSet::new(), HashSet::new(), limits);
+        assert!(matches!(result, Err(GameError::ScriptKilled(_))));
+    }
+
+    #[test]
+    fn test_huge_allocation_is_killed() {
+        let engine = limited_engine(r#"
+function on_turn(turn)
+    local s = string.rep("x", 64 * 1024 * 1024)
+end
+function on_take(item)
+    local t = {}
+    for i = 1, 50000000 do t[i] = i end
+end
+"#);
+        let mut world = world();
+
+        let result = engine.call_hook("on_turn", (1,), &mut world);
+        assert!(matches!(result, Err(GameError::ScriptKilled(_))), "{:?}", result.err());
+
+        let result = engine.call_hook("on_take", ("key",), &mut world);
+        assert!(matches!(result, Err(GameError::ScriptKilled(_))), "{:?}", result.err());
+    }
+
+    #[test]
+    fn test_slow_library_calls_are_killed() {
+        // Few instructions, but every one of them copies a megabyte
+        let limits = ScriptLimits {
+            memory: 16 * 1024 * 1024,
+            instructions: 1_000_000,
+            time: Duration::from_millis(100),
+        };
+        let engine = ScriptEngine::new(r#"
+function on_turn(turn)
+    for i = 1, 100000 do local s = string.rep("x", 1024 * 1024) end
+end
+"#, "hostile.lua", HashSet::new(), HashSet::new(), limits).unwrap();
+
+        let started = Instant::now();
+        let result = engine.call_hook("on_turn", (1,), &mut world());
+        assert!(matches!(&result, Err(GameError::ScriptKilled(reason)) if reason.contains("100ms")), "{:?}", result.err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,316 @@
+use crate::engine::{game::GameError, save::{self, SavedGame}, sqlite_store::SqliteSaveStore};
+use crate::story::loader::Story;
+use directories::ProjectDirs;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const EXTENSION: &str = "save";
+
+/// Somewhere named saves can be kept. `save`, `load`, `delete` and `saves`
+/// only go through this, so every store behaves the same in game.
+pub trait SaveStore {
+    fn write(&self, slot: &str, saved: &SavedGame) -> Result<(), GameError>;
+    fn read(&self, slot: &str) -> Result<SavedGame, GameError>;
+    fn delete(&self, slot: &str) -> Result<(), GameError>;
+    /// Every readable save, newest first.
+    fn list(&self) -> Result<Vec<SlotInfo>, GameError>;
+    /// Where the saves live, to show the player.
+    fn location(&self) -> String;
+
+    /// Keeps a snapshot of the world after a turn. Stores without history ignore it.
+    fn record_turn(&self, _saved: &SavedGame) -> Result<(), GameError> {
+        Ok(())
+    }
+
+    /// Recorded turns, most recent first.
+    fn history(&self, _limit: usize) -> Result<Vec<SavedGame>, GameError> {
+        Ok(Vec::new())
+    }
+}
+
+/// Which kind of save store to use, picked with `--store` on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StoreKind {
+    #[default]
+    Json,
+    Sqlite,
+}
+
+impl StoreKind {
+    pub fn open(self, story: &Story) -> Result<Box<dyn SaveStore>, GameError> {
+        let dir = story_save_dir(story);
+        Ok(match self {
+            StoreKind::Json => Box::new(JsonSaveStore::in_dir(dir)),
+            StoreKind::Sqlite => Box::new(SqliteSaveStore::open(&dir.join("saves.db"))?),
+        })
+    }
+}
+
+impl FromStr for StoreKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "json" => Ok(StoreKind::Json),
+            "sqlite" => Ok(StoreKind::Sqlite),
+            _ => Err(format!("unknown save store `{}` (expected `json` or `sqlite`)", name)),
+        }
+    }
+}
+
+/// A save slot and what's in it.
+pub struct SlotInfo {
+    pub name: String,
+    pub saved: SavedGame,
+}
+
+/// Where a story's saves go: a folder per story under the platform data
+/// directory, e.g. `~/.local/share/nemu/saves/<story>-<fingerprint>/` on Linux.
+pub fn story_save_dir(story: &Story) -> PathBuf {
+    // Fall back to the working directory on systems without a home directory
+    let base = ProjectDirs::from("", "", "nemu")
+        .map(|dirs| dirs.data_dir().to_path_buf())
+        .unwrap_or_else(|| PathBuf::from(".nemu"));
+    base.join("saves").join(story_namespace(story))
+}
+
+/// Keeps each slot as a `<slot>.save` JSON file in one directory.
+pub struct JsonSaveStore {
+    dir: PathBuf,
+}
+
+impl JsonSaveStore {
+    pub fn in_dir(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, slot: &str) -> Result<PathBuf, GameError> {
+        validate_slot_name(slot)?;
+        Ok(self.dir.join(format!("{}.{}", slot, EXTENSION)))
+    }
+}
+
+impl SaveStore for JsonSaveStore {
+    fn write(&self, slot: &str, saved: &SavedGame) -> Result<(), GameError> {
+        let path = self.path(slot)?;
+        fs::create_dir_all(&self.dir).map_err(|e| io_error(&self.dir, e))?;
+        fs::write(&path, saved.to_json()?).map_err(|e| io_error(&path, e))
+    }
+
+    fn read(&self, slot: &str) -> Result<SavedGame, GameError> {
+        let path = self.path(slot)?;
+        if !path.exists() {
+            return Err(no_such_slot(slot));
+        }
+        let json = fs::read_to_string(&path).map_err(|e| io_error(&path, e))?;
+        SavedGame::from_json(&json)
+    }
+
+    fn delete(&self, slot: &str) -> Result<(), GameError> {
+        let path = self.path(slot)?;
+        if !path.exists() {
+            return Err(no_such_slot(slot));
+        }
+        fs::remove_file(&path).map_err(|e| io_error(&path, e))
+    }
+
+    fn list(&self) -> Result<Vec<SlotInfo>, GameError> {
+        // A missing directory just means no saves yet
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(io_error(&self.dir, e)),
+        };
+
+        let mut slots = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(EXTENSION) {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if let Ok(saved) = self.read(name) {
+                slots.push(SlotInfo { name: name.to_string(), saved });
+            }
+        }
+        sort_newest_first(&mut slots);
+        Ok(slots)
+    }
+
+    fn location(&self) -> String {
+        self.dir.display().to_string()
+    }
+}
+
+pub(crate) fn sort_newest_first(slots: &mut [SlotInfo]) {
+    slots.sort_by(|a, b| b.saved.saved_at.cmp(&a.saved.saved_at).then(a.name.cmp(&b.name)));
+}
+
+pub(crate) fn no_such_slot(slot: &str) -> GameError {
+    GameError::Save(format!("there's no save called `{}`", slot))
+}
+
+/// Slot names become file names, so only allow characters that can't escape
+/// the save directory.
+pub fn validate_slot_name(slot: &str) -> Result<(), GameError> {
+    let valid_char = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    if slot.is_empty() || slot.len() > 64 || !slot.chars().all(valid_char) {
+        return Err(GameError::Save(format!(
+            "`{}` isn't a valid save name; use up to 64 letters, numbers, `-` or `_`",
+            slot
+        )));
+    }
+    Ok(())
+}
+
+/// Lists slots with when they were saved, where the player was and how long
+/// they'd played, for `saves` in game and `nemu saves` on the command line.
+pub fn describe_slots(story: &Story, slots: &[SlotInfo]) -> String {
+    if slots.is_empty() {
+        return "No save files found. :0".to_string();
+    }
+
+    let mut output = "Available saves:".to_string();
+    for slot in slots {
+        output.push_str(&format!("\n- {}", describe_slot(story, slot)));
+    }
+    output
+}
+
+/// One line about a slot, e.g. `quicksave (2026-10-18 12:04 UTC) Dusty Parlor, turn 12, played 5m 3s`.
+pub fn describe_slot(story: &Story, slot: &SlotInfo) -> String {
+    let room = story.rooms.get(&slot.saved.current_room)
+        .map_or(slot.saved.current_room.as_str(), |room| room.title.as_str());
+    format!(
+        "{} ({}) {}, turn {}, played {}",
+        slot.name,
+        format_timestamp(slot.saved.saved_at),
+        room,
+        slot.saved.turns,
+        format_duration(slot.saved.playtime)
+    )
+}
+
+// The title, readable for people browsing the folder, plus the start of the
+// story's fingerprint so two stories with the same title, or with titles that
+// don't survive the slug ("Дом"), keep their saves apart
+fn story_namespace(story: &Story) -> String {
+    let slug: String = story.story.title.to_lowercase().chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let slug = slug.split('-').filter(|part| !part.is_empty()).collect::<Vec<_>>().join("-");
+    let slug = if slug.is_empty() { "untitled" } else { slug.as_str() };
+    format!("{}-{}", slug, &save::fingerprint(story)[..8])
+}
+
+fn io_error(path: &Path, error: std::io::Error) -> GameError {
+    GameError::Save(format!("{}: {}", path.display(), error))
+}
+
+/// Formats seconds since the Unix epoch as `YYYY-MM-DD HH:MM UTC`.
+fn format_timestamp(secs: u64) -> String {
+    if secs == 0 {
+        return "unknown time".to_string();
+    }
+    let days = (secs / 86_400) as i64;
+    let minutes = (secs % 86_400) / 60;
+
+    // Days to a civil date, from Howard Hinnant's date algorithms
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02} UTC", year, month, day, minutes / 60, minutes % 60)
+}
+
+fn format_duration(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3_599 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3_600, secs % 3_600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::story::loader::StoryInfo;
+
+    #[test]
+    fn test_slot_names() {
+        assert!(validate_slot_name("quicksave").is_ok());
+        assert!(validate_slot_name("before_the-dragon2").is_ok());
+
+        for bad in ["", "../escape", "a/b", "a\\b", "..", ".hidden", "my save", &"x".repeat(65)] {
+            assert!(validate_slot_name(bad).is_err(), "{:?} should be rejected", bad);
+        }
+    }
+
+    #[test]
+    fn test_story_namespace() {
+        let story = Story {
+            story: StoryInfo {
+                title: "The Haunted House!".to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let namespace = story_namespace(&story);
+        assert!(namespace.starts_with("the-haunted-house-"), "{}", namespace);
+        assert_eq!(namespace.len(), "the-haunted-house-".len() + 8);
+        assert!(story_namespace(&Story::default()).starts_with("untitled-"));
+
+        // Titles that slug the same still get their own folders
+        let titled = |title: &str| Story {
+            story: StoryInfo { title: title.to_string(), ..Default::default() },
+            ..Default::default()
+        };
+        assert_ne!(story_namespace(&titled("Дом")), story_namespace(&titled("幽霊屋敷")));
+        let mut other = titled("The Haunted House!");
+        other.rooms.insert("cellar".to_string(), Default::default());
+        assert_ne!(story_namespace(&other), namespace);
+    }
+
+    #[test]
+    fn test_write_list_and_delete() {
+        let dir = Path::new("test_write_list_and_delete");
+        let slots = JsonSaveStore::in_dir(dir);
+        assert!(slots.list().unwrap().is_empty());
+
+        let older = SavedGame { current_room: "start".to_string(), saved_at: 100, ..Default::default() };
+        let newer = SavedGame { current_room: "start".to_string(), saved_at: 200, ..Default::default() };
+        slots.write("older", &older).unwrap();
+        slots.write("newer", &newer).unwrap();
+        assert!(slots.write("../outside", &older).is_err());
+
+        let names: Vec<String> = slots.list().unwrap().into_iter().map(|slot| slot.name).collect();
+        assert_eq!(names, vec!["newer".to_string(), "older".to_string()]);
+        assert_eq!(slots.read("older").unwrap(), older);
+
+        slots.delete("older").unwrap();
+        assert!(slots.read("older").is_err());
+        assert!(slots.delete("older").is_err());
+
+        // Clean up
+        fs::remove_dir_all(dir).expect("Failed to remove test directory");
+    }
+
+    #[test]
+    fn test_formatting() {
+        assert_eq!(format_timestamp(0), "unknown time");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00 UTC");
+        assert_eq!(format_timestamp(1_792_325_040), "2026-10-18 12:04 UTC");
+        assert_eq!(format_duration(42), "42s");
+        assert_eq!(format_duration(303), "5m 3s");
+        assert_eq!(format_duration(7_380), "2h 3m");
+    }
+}
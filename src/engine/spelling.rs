@@ -0,0 +1,81 @@
+/// Edit distance between two words, counting a swap of neighbouring letters
+/// as one edit so "tkae" is one away from "take".
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // d[i][j] is the distance between the first i letters of a and the first j of b
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    d[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// The known word `word` is most likely a misspelling of. Short words are
+/// left alone, longer ones may be two edits off, and a tie between different
+/// words means there's no good guess.
+pub fn closest<'a>(word: &str, known: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let length = word.chars().count();
+    let allowed = match length {
+        0..=2 => return None,
+        3..=4 => 1,
+        _ => 2,
+    };
+
+    let mut best: Option<(usize, &str)> = None;
+    let mut tied = false;
+    for candidate in known {
+        let distance = edit_distance(word, candidate);
+        if distance > allowed {
+            continue;
+        }
+        match best {
+            Some((best_distance, best_word)) if distance == best_distance && candidate != best_word => tied = true,
+            Some((best_distance, _)) if distance >= best_distance => {}
+            _ => {
+                best = Some((distance, candidate));
+                tied = false;
+            }
+        }
+    }
+    best.filter(|_| !tied).map(|(_, word)| word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("take", "take"), 0);
+        assert_eq!(edit_distance("tkae", "take"), 1);
+        assert_eq!(edit_distance("nrth", "north"), 1);
+        assert_eq!(edit_distance("lamp", "lump"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn test_closest() {
+        let verbs = ["take", "drop", "open", "close", "examine"];
+        assert_eq!(closest("tkae", verbs), Some("take"));
+        assert_eq!(closest("exmaine", verbs), Some("examine"));
+        assert_eq!(closest("xyzzy", verbs), None);
+
+        // Too short to guess, and ties are no guess at all
+        assert_eq!(closest("tk", verbs), None);
+        assert_eq!(closest("lmp", ["lamp", "limp"]), None);
+        assert_eq!(closest("lmp", ["lamp", "lamp"]), Some("lamp"));
+    }
+}
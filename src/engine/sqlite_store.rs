@@ -0,0 +1,199 @@
+use crate::engine::{
+    game::GameError,
+    save::SavedGame,
+    slots::{no_such_slot, sort_newest_first, validate_slot_name, SaveStore, SlotInfo},
+};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::fs;
+use std::path::Path;
+
+/// How many turns of history to keep before the oldest are dropped.
+const HISTORY_LIMIT: usize = 200;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS saves (
+    slot TEXT PRIMARY KEY,
+    saved_at INTEGER NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    turn INTEGER NOT NULL,
+    saved_at INTEGER NOT NULL,
+    data TEXT NOT NULL
+);
+";
+
+/// Keeps every slot and a rolling per-turn history for one story in a
+/// single SQLite database. Rows hold the same JSON as the file store.
+pub struct SqliteSaveStore {
+    connection: Connection,
+    location: String,
+}
+
+impl SqliteSaveStore {
+    pub fn open(path: &Path) -> Result<Self, GameError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| GameError::Save(format!("{}: {}", dir.display(), e)))?;
+        }
+        let connection = Connection::open(path).map_err(db_error)?;
+        Self::with_connection(connection, path.display().to_string())
+    }
+
+    pub fn open_in_memory() -> Result<Self, GameError> {
+        Self::with_connection(Connection::open_in_memory().map_err(db_error)?, ":memory:".to_string())
+    }
+
+    fn with_connection(connection: Connection, location: String) -> Result<Self, GameError> {
+        connection.execute_batch(SCHEMA).map_err(db_error)?;
+        Ok(Self { connection, location })
+    }
+}
+
+impl SaveStore for SqliteSaveStore {
+    fn write(&self, slot: &str, saved: &SavedGame) -> Result<(), GameError> {
+        validate_slot_name(slot)?;
+        self.connection.execute(
+            "INSERT INTO saves (slot, saved_at, data) VALUES (?1, ?2, ?3)
+             ON CONFLICT(slot) DO UPDATE SET saved_at = excluded.saved_at, data = excluded.data",
+            params![slot, saved.saved_at as i64, saved.to_json()?],
+        ).map_err(db_error)?;
+        Ok(())
+    }
+
+    fn read(&self, slot: &str) -> Result<SavedGame, GameError> {
+        validate_slot_name(slot)?;
+        let json: Option<String> = self.connection
+            .query_row("SELECT data FROM saves WHERE slot = ?1", params![slot], |row| row.get(0))
+            .optional()
+            .map_err(db_error)?;
+        SavedGame::from_json(&json.ok_or_else(|| no_such_slot(slot))?)
+    }
+
+    fn delete(&self, slot: &str) -> Result<(), GameError> {
+        validate_slot_name(slot)?;
+        let deleted = self.connection
+            .execute("DELETE FROM saves WHERE slot = ?1", params![slot])
+            .map_err(db_error)?;
+        if deleted == 0 {
+            return Err(no_such_slot(slot));
+        }
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<SlotInfo>, GameError> {
+        let mut statement = self.connection.prepare("SELECT slot, data FROM saves").map_err(db_error)?;
+        let rows = statement
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+            .map_err(db_error)?;
+
+        // Like the file store, skip saves that can't be read rather than failing the listing
+        let mut slots = Vec::new();
+        for (name, json) in rows.flatten() {
+            if let Ok(saved) = SavedGame::from_json(&json) {
+                slots.push(SlotInfo { name, saved });
+            }
+        }
+        sort_newest_first(&mut slots);
+        Ok(slots)
+    }
+
+    fn location(&self) -> String {
+        self.location.clone()
+    }
+
+    fn record_turn(&self, saved: &SavedGame) -> Result<(), GameError> {
+        self.connection.execute(
+            "INSERT INTO history (turn, saved_at, data) VALUES (?1, ?2, ?3)",
+            params![saved.turns, saved.saved_at as i64, saved.to_json()?],
+        ).map_err(db_error)?;
+        self.connection.execute(
+            "DELETE FROM history WHERE id NOT IN (SELECT id FROM history ORDER BY id DESC LIMIT ?1)",
+            params![HISTORY_LIMIT as i64],
+        ).map_err(db_error)?;
+        Ok(())
+    }
+
+    fn history(&self, limit: usize) -> Result<Vec<SavedGame>, GameError> {
+        let mut statement = self.connection
+            .prepare("SELECT data FROM history ORDER BY id DESC LIMIT ?1")
+            .map_err(db_error)?;
+        let rows = statement
+            .query_map(params![limit as i64], |row| row.get::<_, String>(0))
+            .map_err(db_error)?;
+
+        let mut history = Vec::new();
+        for json in rows {
+            history.push(SavedGame::from_json(&json.map_err(db_error)?)?);
+        }
+        Ok(history)
+    }
+}
+
+fn db_error(error: rusqlite::Error) -> GameError {
+    GameError::Save(format!("save database: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved(room: &str, turns: u32, saved_at: u64) -> SavedGame {
+        SavedGame {
+            current_room: room.to_string(),
+            turns,
+            saved_at,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_slots() {
+        let store = SqliteSaveStore::open_in_memory().unwrap();
+        assert!(store.list().unwrap().is_empty());
+
+        store.write("older", &saved("start", 1, 100)).unwrap();
+        store.write("newer", &saved("vault", 2, 200)).unwrap();
+        assert!(store.write("../outside", &saved("start", 1, 100)).is_err());
+
+        let names: Vec<String> = store.list().unwrap().into_iter().map(|slot| slot.name).collect();
+        assert_eq!(names, vec!["newer".to_string(), "older".to_string()]);
+        assert_eq!(store.read("newer").unwrap(), saved("vault", 2, 200));
+
+        // Saving to an existing slot replaces it
+        store.write("older", &saved("vault", 5, 300)).unwrap();
+        assert_eq!(store.read("older").unwrap().turns, 5);
+        assert_eq!(store.list().unwrap().len(), 2);
+
+        store.delete("older").unwrap();
+        assert!(store.read("older").is_err());
+        assert!(store.delete("older").is_err());
+    }
+
+    #[test]
+    fn test_history() {
+        let store = SqliteSaveStore::open_in_memory().unwrap();
+        for turn in 1..=(HISTORY_LIMIT as u32 + 5) {
+            store.record_turn(&saved("start", turn, 0)).unwrap();
+        }
+
+        let recent = store.history(3).unwrap();
+        let turns: Vec<u32> = recent.iter().map(|saved| saved.turns).collect();
+        assert_eq!(turns, vec![HISTORY_LIMIT as u32 + 5, HISTORY_LIMIT as u32 + 4, HISTORY_LIMIT as u32 + 3]);
+        assert_eq!(store.history(HISTORY_LIMIT * 2).unwrap().len(), HISTORY_LIMIT);
+    }
+
+    #[test]
+    fn test_database_file() {
+        let dir = Path::new("test_sqlite_database_file");
+        let path = dir.join("saves.db");
+
+        SqliteSaveStore::open(&path).unwrap().write("slot", &saved("start", 1, 100)).unwrap();
+        let reopened = SqliteSaveStore::open(&path).unwrap();
+        assert_eq!(reopened.read("slot").unwrap().current_room, "start");
+
+        // Clean up
+        drop(reopened);
+        fs::remove_dir_all(dir).expect("Failed to remove test directory");
+    }
+}
@@ -1,16 +1,16 @@
 use clap::Parser;
+use nemu::{cli, engine::slots::StoreKind};
 use std::path::PathBuf;
 
-mod cli;
-mod engine;
-mod story;
-
 #[derive(Parser)]
 #[command(name = "nemu", about = "Nemu - Text Adventure Engine")]
 enum NemuCommand {
     /// Play a story
     Play {
         file: PathBuf,
+        /// Where to keep saves: `json` files or a `sqlite` database
+        #[arg(long, default_value = "json")]
+        store: StoreKind,
     },
     /// Create a new story template
     Create {
@@ -20,6 +20,13 @@ enum NemuCommand {
     Validate {
         file: PathBuf,
     },
+    /// List the saved games for a story
+    Saves {
+        file: PathBuf,
+        /// Which save store to list: `json` or `sqlite`
+        #[arg(long, default_value = "json")]
+        store: StoreKind,
+    },
 }
 
 #[tokio::main]
@@ -27,8 +34,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let command = NemuCommand::parse();
     
     match command {
-        NemuCommand::Play { file } => {
-            cli::play::handle_play(file).await?;
+        NemuCommand::Play { file, store } => {
+            cli::play::handle_play(file, store).await?;
         },
         NemuCommand::Create { name } => {
             cli::create::handle_create(name).await?;
@@ -36,6 +43,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         NemuCommand::Validate { file } => {
             cli::validate::handle_validate(file).await?;
         },
+        NemuCommand::Saves { file, store } => {
+            cli::saves::handle_saves(file, store).await?;
+        },
     }
     
     Ok(())
@@ -1,20 +1,155 @@
-use crate::engine::{room::Room, item::Item};
+use crate::engine::{dialogue, direction::{DirectionDef, Directions}, exit::Exit, room::Room, item::Item, npc::Npc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct Story {
     pub story: StoryInfo,
     pub rooms: HashMap<String, Room>,
     pub items: HashMap<String, Item>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub npcs: HashMap<String, Npc>,
+    #[serde(default)]
+    pub config: StoryConfig,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub verbs: HashMap<String, Verb>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub directions: HashMap<String, DirectionDef>, // extra directions, or more words for built-in ones
+    #[serde(skip)]
+    pub script_source: Option<String>, // contents of `story.script`, read by `load_story`
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct StoryInfo {
     pub title: String,
     pub start_room: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>, // Lua file, relative to the story file
+}
+
+/// Optional `[config]` settings that tune the engine for a story.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct StoryConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_inventory_weight: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_inventory_items: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_save_interval: Option<u64>, // seconds between autosaves
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_save_turns: Option<u32>, // turns between autosaves
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub save_undo_history: bool, // keep `undo` working across save and load
+    #[serde(default)]
+    pub typo_correction: TypoCorrection,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub reverse_exits: bool, // give exits a way back in the opposite direction
+}
+
+/// What to do when a command fails and looks like a typo, e.g. `tkae lamp`.
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TypoCorrection {
+    Off,
+    #[default]
+    Suggest, // "Did you mean "take lamp"?"
+    Correct, // run the corrected command straight away
+}
+
+/// A verb the story adds, or more words for a built-in one:
+///
+/// ```toml
+/// [verbs.pray]
+/// aliases = ["kneel", "worship"]
+/// message = "Nothing seems to happen."
+/// ```
+///
+/// Items and rooms answer story verbs with `reactions`; `message` is the
+/// reply when none of them does.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Verb {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl Story {
+    /// The story verb `word` names, if it's one of them or an alias.
+    pub fn verb_for(&self, word: &str) -> Option<&str> {
+        self.verbs.iter()
+            .find(|(name, verb)| {
+                name.eq_ignore_ascii_case(word) || verb.aliases.iter().any(|alias| alias.eq_ignore_ascii_case(word))
+            })
+            .map(|(name, _)| name.as_str())
+    }
+    
+    /// Adds the way back for every exit that isn't `one_way`, in the
+    /// opposite direction: `north = "hallway"` gives the hallway `south`
+    /// back here. Rooms that already have an exit that way, or any exit
+    /// back, are left alone, as is a way back two rooms would both want.
+    pub fn add_reverse_exits(&mut self) {
+        let directions = Directions::for_story(&self.directions);
+        let mut wanted: HashMap<(String, String), Vec<String>> = HashMap::new(); // (room, direction) -> rooms it would lead to
+        for (room_id, room) in &self.rooms {
+            for (direction, exit) in &room.exits {
+                let (Some(back), Some(target)) = (directions.opposite(direction), self.rooms.get(&exit.to)) else {
+                    continue;
+                };
+                let has_way_back = target.exits.iter()
+                    .any(|(name, reverse)| reverse.to == *room_id || directions.same(name, back));
+                if !exit.one_way && !has_way_back {
+                    wanted.entry((exit.to.clone(), back.to_string())).or_default().push(room_id.clone());
+                }
+            }
+        }
+        
+        for ((room_id, direction), to) in wanted {
+            if let ([to], Some(room)) = (to.as_slice(), self.rooms.get_mut(&room_id)) {
+                room.exits.insert(direction, Exit::new(to));
+            }
+        }
+    }
+    
+    /// Exits that look like a forgotten or mistyped way back: the room they
+    /// lead to has no exit back at all, or its exit the opposite way goes
+    /// somewhere else. `one_way` exits aren't reported.
+    pub fn asymmetric_exits(&self) -> Vec<String> {
+        let directions = Directions::for_story(&self.directions);
+        let mut rooms: Vec<(&String, &Room)> = self.rooms.iter().collect();
+        rooms.sort_by_key(|(room_id, _)| *room_id);
+        
+        let mut warnings = Vec::new();
+        for (room_id, room) in rooms {
+            let mut exits: Vec<(&String, &Exit)> = room.exits.iter().collect();
+            exits.sort_by_key(|(direction, _)| *direction);
+            for (direction, exit) in exits {
+                let Some(target) = self.rooms.get(&exit.to) else {
+                    continue;
+                };
+                if exit.one_way || exit.to == *room_id {
+                    continue;
+                }
+                let reverse = directions.opposite(direction)
+                    .and_then(|back| target.exits.iter().find(|(name, _)| directions.same(name, back)));
+                match reverse {
+                    Some((back, reverse)) if reverse.to != *room_id => warnings.push(format!(
+                        "`{}` leads {} to `{}`, but {} from `{}` leads to `{}`",
+                        room_id, direction, exit.to, back, exit.to, reverse.to
+                    )),
+                    None if !target.exits.values().any(|reverse| reverse.to == *room_id) => warnings.push(format!(
+                        "`{}` leads {} to `{}`, but there's no way back",
+                        room_id, direction, exit.to
+                    )),
+                    _ => {}
+                }
+            }
+        }
+        warnings
+    }
 }
 
 pub fn load_story(path: &Path) -> Result<Story, Box<dyn std::error::Error>> {
@@ -25,7 +160,7 @@ pub fn load_story(path: &Path) -> Result<Story, Box<dyn std::error::Error>> {
         for entry in fs::read_dir(path)? {
             let entry = entry?;
             let file_path_entry = entry.path();
-            if file_path_entry.extension().map_or(false, |ext| ext == "toml") {
+            if file_path_entry.extension().is_some_and(|ext| ext == "toml") {
                 file_path = file_path_entry;
                 break;
             }
@@ -38,13 +173,58 @@ pub fn load_story(path: &Path) -> Result<Story, Box<dyn std::error::Error>> {
     }
     
     let content = fs::read_to_string(&file_path)?;
-    let story: Story = toml::from_str(&content)?;
+    let mut story: Story = toml::from_str(&content)?;
+    if story.config.reverse_exits {
+        story.add_reverse_exits();
+    }
+    
+    if let Some(script) = &story.story.script {
+        let script_path = file_path.parent().unwrap_or(Path::new(".")).join(script);
+        let source = fs::read_to_string(&script_path)
+            .map_err(|e| format!("Could not read script {:?}: {}", script_path, e))?;
+        story.script_source = Some(source);
+    }
     
     // Validate the story has a start room
     if !story.rooms.contains_key(&story.story.start_room) {
         return Err("Start room does not exist in story".into());
     }
     
+    // Every exit has to lead somewhere, which also catches a misspelt room
+    // field like `descripton = "..."` being read as an exit
+    let mut room_ids: Vec<&String> = story.rooms.keys().collect();
+    room_ids.sort();
+    for room_id in room_ids {
+        let mut exits: Vec<(&String, &Exit)> = story.rooms[room_id].exits.iter().collect();
+        exits.sort_by_key(|(direction, _)| *direction);
+        if let Some((direction, exit)) = exits.into_iter().find(|(_, exit)| !story.rooms.contains_key(&exit.to)) {
+            return Err(format!("Room `{}` has an exit `{}` to an unknown room `{}`", room_id, direction, exit.to).into());
+        }
+    }
+    
+    // NPCs have to start, patrol and wander somewhere real
+    for (npc_id, npc) in &story.npcs {
+        let mut rooms = std::iter::once(&npc.location).chain(&npc.patrol).chain(&npc.wander);
+        if let Some(room_id) = rooms.find(|room_id| !story.rooms.contains_key(*room_id)) {
+            return Err(format!("NPC `{}` refers to an unknown room `{}`", npc_id, room_id).into());
+        }
+        // Patrols walk through exits, from the start to the first room and round the route
+        let mut legs: Vec<(&String, &String)> = npc.patrol.iter().zip(npc.patrol.iter().cycle().skip(1)).collect();
+        if let Some(first) = npc.patrol.first().filter(|_| !npc.patrol.contains(&npc.location)) {
+            legs.push((&npc.location, first));
+        }
+        let connected = |from: &String, to: &String| from == to || story.rooms[from].exits.values().any(|exit| exit.to == *to);
+        if let Some((from, to)) = legs.into_iter().find(|(from, to)| !connected(from, to)) {
+            return Err(format!("NPC `{}` patrols from `{}` to `{}`, but no exit leads there", npc_id, from, to).into());
+        }
+        dialogue::check(&npc.dialogue, |item_id| story.items.contains_key(item_id))
+            .map_err(|e| format!("NPC `{}`: {}", npc_id, e))?;
+        let mut traded = npc.trades.iter().flat_map(|trade| std::iter::once(&trade.wants).chain(&trade.gives));
+        if let Some(item_id) = traded.find(|item_id| !story.items.contains_key(*item_id)) {
+            return Err(format!("NPC `{}` trades an unknown item `{}`", npc_id, item_id).into());
+        }
+    }
+    
     Ok(story)
 }// TOML parser working :0
 
@@ -116,6 +296,262 @@ description = "A test key."
         fs::remove_file(test_path).expect("Failed to remove test file");
     }
 
+    #[test]
+    fn test_load_shipped_stories() {
+        // haunted_house uses `exits = { ... }`, ancient_temple uses bare direction keys
+        let haunted = load_story(Path::new("haunted_house.toml")).expect("haunted_house.toml should load");
+        assert_eq!(haunted.rooms["hallway"].exits["east"].to, "parlor");
+        assert_eq!(haunted.npcs["ghost"].location, "parlor");
+        assert_eq!(haunted.npcs["ghost"].dialogue["start"].choices.len(), 4);
+
+        let temple = load_story(Path::new("ancient_temple.toml")).expect("ancient_temple.toml should load");
+        assert_eq!(temple.rooms["temple_entrance"].exits["inside"].to, "temple_hall");
+        assert_eq!(temple.rooms["temple_hall"].exits["back"].to, "temple_entrance"); // from `reverse_exits`
+        assert_eq!(temple.npcs["monkey"].trades[0].gives, ["ancient_coin"]);
+        assert!(temple.asymmetric_exits().is_empty());
+        assert!(haunted.asymmetric_exits().is_empty());
+        assert!(temple.script_source.is_some());
+    }
+
+    #[test]
+    fn test_load_story_with_script() {
+        let dir = Path::new("test_load_story_with_script");
+        fs::create_dir_all(dir).expect("Failed to create test directory");
+        fs::write(dir.join("story.toml"), r#"
+[story]
+title = "Scripted Story"
+start_room = "start"
+script = "story.lua"
+
+[rooms.start]
+title = "Start Room"
+description = "A starting room."
+items = []
+
+[items.key]
+name = "Test Key"
+description = "A test key."
+"#).expect("Failed to write test file");
+        fs::write(dir.join("story.lua"), "function on_turn(turn) end").expect("Failed to write test script");
+
+        let story = load_story(dir).expect("story with script should load");
+        assert_eq!(story.script_source.as_deref(), Some("function on_turn(turn) end"));
+
+        // A missing script is an error
+        fs::remove_file(dir.join("story.lua")).expect("Failed to remove test script");
+        assert!(load_story(dir).is_err());
+
+        // Clean up
+        fs::remove_dir_all(dir).expect("Failed to remove test directory");
+    }
+
+    #[test]
+    fn test_load_story_config() {
+        let test_content = r#"
+[story]
+title = "Test Story"
+start_room = "start"
+
+[config]
+max_inventory_weight = 100
+max_inventory_items = 5
+auto_save_interval = 300
+auto_save_turns = 20
+enable_combat = false
+
+[rooms.start]
+title = "Start Room"
+description = "A starting room."
+items = []
+
+[items.key]
+name = "Test Key"
+description = "A test key."
+"#;
+
+        let test_path = "test_load_story_config.toml";
+        fs::write(test_path, test_content).expect("Failed to write test file");
+
+        let story = load_story(Path::new(test_path)).expect("story with config should load");
+        assert_eq!(story.config.max_inventory_weight, Some(100));
+        assert_eq!(story.config.max_inventory_items, Some(5));
+        assert_eq!(story.config.auto_save_interval, Some(300));
+        assert_eq!(story.config.auto_save_turns, Some(20));
+        assert_eq!(story.config.typo_correction, TypoCorrection::Suggest);
+
+        // Clean up
+        fs::remove_file(test_path).expect("Failed to remove test file");
+    }
+
+    #[test]
+    fn test_story_verbs_and_directions() {
+        let story: Story = toml::from_str(r#"
+[story]
+title = "Test Story"
+start_room = "start"
+
+[verbs.pray]
+aliases = ["kneel", "Worship"]
+message = "Nothing seems to happen."
+
+[verbs.take]
+aliases = ["grab"]
+
+[directions]
+inside = ["in", "enter"]
+
+[rooms.start]
+title = "Start Room"
+description = "A starting room."
+items = []
+
+[items]
+"#).unwrap();
+
+        assert_eq!(story.verb_for("pray"), Some("pray"));
+        assert_eq!(story.verb_for("worship"), Some("pray"));
+        assert_eq!(story.verb_for("GRAB"), Some("take"));
+        assert_eq!(story.verb_for("dance"), None);
+        assert_eq!(story.verbs["pray"].message.as_deref(), Some("Nothing seems to happen."));
+        assert_eq!(story.directions["inside"].aliases, ["in", "enter"]);
+    }
+
+    #[test]
+    fn test_reverse_exits() {
+        let mut story: Story = toml::from_str(r#"
+[story]
+title = "Test Story"
+start_room = "cellar"
+
+[directions]
+inside = { opposite = "back" }
+
+[rooms.cellar]
+title = "Cellar"
+description = "A damp cellar."
+items = []
+up = "kitchen"
+inside = "crawlspace"
+northeast = { to = "pit", one_way = true }
+
+[rooms.kitchen]
+title = "Kitchen"
+description = "A kitchen."
+items = []
+window = "garden"
+
+[rooms.garden]
+title = "Garden"
+description = "A garden."
+items = []
+out = "kitchen"
+
+[rooms.crawlspace]
+title = "Crawlspace"
+description = "A cramped crawlspace."
+items = []
+
+[rooms.pit]
+title = "Pit"
+description = "A deep pit."
+items = []
+
+[items]
+"#).unwrap();
+
+        // `window` has no opposite, but `out` leads back so that's fine
+        assert_eq!(story.asymmetric_exits(), [
+            "`cellar` leads inside to `crawlspace`, but there's no way back",
+            "`cellar` leads up to `kitchen`, but there's no way back",
+        ]);
+
+        story.add_reverse_exits();
+        assert_eq!(story.rooms["kitchen"].exits["down"].to, "cellar");
+        assert_eq!(story.rooms["crawlspace"].exits["back"].to, "cellar");
+        assert!(story.rooms["pit"].exits.is_empty()); // one way
+        assert_eq!(story.rooms["garden"].exits.len(), 1);
+        assert_eq!(story.rooms["kitchen"].exits.len(), 2);
+        assert!(story.asymmetric_exits().is_empty());
+
+        // A way back that goes somewhere else is reported too
+        story.rooms.get_mut("garden").unwrap().exits.insert("east".to_string(), Exit::new("kitchen"));
+        story.rooms.get_mut("kitchen").unwrap().exits.insert("west".to_string(), Exit::new("cellar"));
+        assert!(story.asymmetric_exits().contains(&"`garden` leads east to `kitchen`, but west from `kitchen` leads to `cellar`".to_string()));
+    }
+
+    #[test]
+    fn test_npc_rooms_must_exist() {
+        let story = |npc: &str| format!(r#"
+[story]
+title = "Test Story"
+start_room = "start"
+
+[rooms.start]
+title = "Start Room"
+description = "A starting room."
+items = []
+
+[items]
+
+[npcs.cat]
+name = "cat"
+description = "A grey cat."
+{}
+"#, npc);
+
+        let test_path = "test_npc_rooms_must_exist.toml";
+        fs::write(test_path, story(r#"location = "start""#)).expect("Failed to write test file");
+        assert!(load_story(Path::new(test_path)).is_ok());
+
+        fs::write(test_path, story("location = \"start\"\nwander = [\"start\", \"attic\"]")).expect("Failed to write test file");
+        let error = load_story(Path::new(test_path)).unwrap_err().to_string();
+        assert_eq!(error, "NPC `cat` refers to an unknown room `attic`");
+
+        let attic = "\n[rooms.attic]\ntitle = \"Attic\"\ndescription = \"Dusty.\"\nitems = []";
+        fs::write(test_path, story(&format!("location = \"start\"\npatrol = [\"start\", \"attic\"]\n{}", attic))).expect("Failed to write test file");
+        let error = load_story(Path::new(test_path)).unwrap_err().to_string();
+        assert_eq!(error, "NPC `cat` patrols from `start` to `attic`, but no exit leads there");
+
+        fs::write(test_path, story("location = \"start\"\ntrades = [{ wants = \"fish\" }]")).expect("Failed to write test file");
+        let error = load_story(Path::new(test_path)).unwrap_err().to_string();
+        assert_eq!(error, "NPC `cat` trades an unknown item `fish`");
+
+        // Clean up
+        fs::remove_file(test_path).expect("Failed to remove test file");
+    }
+
+    #[test]
+    fn test_exits_must_lead_to_known_rooms() {
+        let test_content = r#"
+[story]
+title = "Test Story"
+start_room = "start"
+
+[rooms.start]
+title = "Start Room"
+description = "A starting room."
+short_descripton = "The start."
+items = []
+
+[items]
+"#;
+
+        let test_path = "test_exits_must_lead_to_known_rooms.toml";
+        fs::write(test_path, test_content).expect("Failed to write test file");
+        let error = load_story(Path::new(test_path)).unwrap_err().to_string();
+        fs::remove_file(test_path).expect("Failed to remove test file");
+        assert_eq!(error, "Room `start` has an exit `short_descripton` to an unknown room `The start.`");
+    }
+
+    #[test]
+    fn test_typo_correction_setting() {
+        let config: StoryConfig = toml::from_str(r#"typo_correction = "correct""#).unwrap();
+        assert_eq!(config.typo_correction, TypoCorrection::Correct);
+        let config: StoryConfig = toml::from_str(r#"typo_correction = "off""#).unwrap();
+        assert_eq!(config.typo_correction, TypoCorrection::Off);
+        assert!(toml::from_str::<StoryConfig>(r#"typo_correction = "sometimes""#).is_err());
+    }
+
     #[test]
     fn test_load_invalid_toml() {
         let test_content = r#"